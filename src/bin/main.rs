@@ -1,5 +1,16 @@
-use cda_cache_sim::user_input::all_user_input;
-use cda_cache_sim::cache::Cache;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use cda_cache_sim::user_input::{self, all_user_input, ReplacementPolicy, UserInput};
+use cda_cache_sim::cache::replacement::{self, AdaptiveInsertion, LruPolicy, RripFlavor, RripPolicy};
+use cda_cache_sim::cache::prefetch;
+use cda_cache_sim::cache::{trace, Access, AccessKind, Cache, EnergyParams, IndexFunction, Latencies, MissTraceWriter, Mode, Prefetcher, TraceSource};
+use cda_cache_sim::config::{presets, Config};
+use cda_cache_sim::generator::{Generator, Pattern};
+use cda_cache_sim::trace_tools::{self, TimestampSource, TraceStats};
+use cda_cache_sim::coherence::{self, MultiCore, Protocol};
+use cda_cache_sim::vm::{self, Mapping, Mmu};
 
 use plotly::{Plot, Scatter};
 
@@ -42,17 +53,722 @@ fn fancy_ascii_art(){
 	});
 }
 
+// the command line arguments, split up into --flag value pairs, --switches and everything else
+//
+// e.g. --cache-size-exp 10 --reference trace_files/read01.trace
+struct Args {
+    flags: HashMap<String, String>,
+    switches: Vec<String>,
+    positional: Vec<String>,
+}
+
+impl Args {
+    // flags that don't take a value
+    const SWITCHES: [&'static str; 3] = ["--reference", "--sharing", "--energy"];
+
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Args { flags: HashMap::new(), switches: Vec::new(), positional: Vec::new() };
+
+        while let Some(arg) = args.next() {
+            if Args::SWITCHES.contains(&arg.as_str()) {
+                parsed.switches.push(arg);
+            } else if arg.starts_with("--") {
+                let value = args.next().ok_or(format!("{} needs a value", arg))?;
+                parsed.flags.insert(arg, value);
+            } else {
+                parsed.positional.push(arg);
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    fn switch(&self, name: &str) -> bool {
+        self.switches.iter().any(|switch| switch == name)
+    }
+
+    // the value of a flag that has to be a number
+    fn number(&self, name: &str) -> Result<Option<u32>, String> {
+        self.flags
+            .get(name)
+            .map(|value| value.parse().map_err(|e| format!("{} {}: {}", name, value, e)))
+            .transpose()
+    }
+
+    // the value of a flag that has to be a number, but not necessarily a whole one
+    fn float(&self, name: &str) -> Result<Option<f64>, String> {
+        self.flags
+            .get(name)
+            .map(|value| value.parse().map_err(|e| format!("{} {}: {}", name, value, e)))
+            .transpose()
+    }
+
+    // an address in hex (with or without the 0x)
+    fn hex(&self, name: &str) -> Result<Option<u32>, String> {
+        self.flags
+            .get(name)
+            .map(|value| {
+                let hex = value.strip_prefix("0x").unwrap_or(value);
+                u32::from_str_radix(hex, 16).map_err(|e| format!("{} {}: {}", name, value, e))
+            })
+            .transpose()
+    }
+
+    // a size in bytes, with an optional K or M on the end (e.g. 1536K)
+    fn size(&self, name: &str) -> Result<Option<u32>, String> {
+        self.flags
+            .get(name)
+            .map(|value| user_input::parse_size(value).map_err(|e| format!("{}: {}", name, e)))
+            .transpose()
+    }
+
+    // the cache config from --cache-size, --line-size, --ways and --policy, for sizes and ways
+    // that aren't powers of 2 (--ways full is fully associative)
+    fn user_input_from_sizes(&self) -> Result<Option<UserInput>, String> {
+        let policy = self.flags.get("--policy").map(String::as_str).unwrap_or("l");
+
+        match (self.size("--cache-size")?, self.size("--line-size")?, self.flags.get("--ways")) {
+            (None, None, None) => Ok(None),
+            (Some(cache_size), Some(line_size), Some(ways)) => {
+                let ways = match ways.as_str() {
+                    "full" => cache_size.checked_div(line_size).unwrap_or(0),
+                    ways => ways.parse().map_err(|e| format!("--ways {}: {}", ways, e))?,
+                };
+                UserInput::from_sizes(cache_size, line_size, ways, policy).map(Some)
+            }
+            _ => Err("--cache-size, --line-size and --ways go together".into()),
+        }
+    }
+
+    // the cache config from --cache-size-exp, --line-size-exp, --type, --ways-exp and --policy
+    // (or the --cache-size ones above)
+    //
+    // None if none of them were given (then we ask at the prompts instead)
+    fn user_input(&self) -> Result<Option<UserInput>, String> {
+        if let Some(ui) = self.user_input_from_sizes()? {
+            if self.flags.contains_key("--cache-size-exp") || self.flags.contains_key("--type") {
+                return Err("use --cache-size, --line-size and --ways or the exponent flags, not both".into());
+            }
+            return Ok(Some(ui));
+        }
+
+        let cache_size_exp = self.number("--cache-size-exp")?;
+        let line_size_exp = self.number("--line-size-exp")?;
+        let cache_type = self.flags.get("--type");
+
+        match (cache_size_exp, line_size_exp, cache_type) {
+            (None, None, None) => Ok(None),
+            (Some(cache_size_exp), Some(line_size_exp), Some(cache_type)) => {
+                let ways_exp = self.number("--ways-exp")?;
+                let policy = self.flags.get("--policy").map(String::as_str).unwrap_or("l");
+                UserInput::from_answers(cache_size_exp, line_size_exp, cache_type, ways_exp, policy).map(Some)
+            }
+            _ => Err("--cache-size-exp, --line-size-exp and --type go together".into()),
+        }
+    }
+}
+
+// the prefetcher from --prefetch (none if it wasn't given)
+fn prefetcher(args: &Args) -> Result<Option<Box<dyn Prefetcher>>, String> {
+    let Some(name) = args.flags.get("--prefetch") else {
+        return Ok(None);
+    };
+
+    prefetch::from_name(name).map(Some).map_err(|e| format!("--prefetch: {}", e))
+}
+
+// the set index function from --index (bit slicing if it wasn't given), randomized takes its
+// key from --seed
+fn index_function(args: &Args) -> Result<IndexFunction, String> {
+    let Some(name) = args.flags.get("--index") else {
+        return Ok(IndexFunction::BitSlice);
+    };
+
+    match IndexFunction::try_from(name.as_str())? {
+        IndexFunction::Randomized { .. } => Ok(IndexFunction::Randomized { key: args.number("--seed")?.unwrap_or(0) as u64 }),
+        function => Ok(function),
+    }
+}
+
+// the latencies from --hit-latency, --miss-penalty and --memory-latency (the defaults for
+// anything that wasn't given)
+fn latencies(args: &Args) -> Result<Latencies, String> {
+    let defaults = Latencies::default();
+    Ok(Latencies {
+        hit_latency: args.number("--hit-latency")?.unwrap_or(defaults.hit_latency),
+        miss_penalty: args.number("--miss-penalty")?.unwrap_or(defaults.miss_penalty),
+        memory_latency: args.number("--memory-latency")?.unwrap_or(defaults.memory_latency),
+        ..defaults
+    })
+}
+
+// the Mmu from --mapping identity|random|coloring, --tlb 64:4,1024:8 (entries:ways for every
+// level), --page-size-exp (12 by default) and --seed (for random)
+//
+// None if neither --mapping nor --tlb was given
+fn mmu(args: &Args, ui: &UserInput) -> Result<Option<Mmu>, String> {
+    if !args.flags.contains_key("--mapping") && !args.flags.contains_key("--tlb") {
+        return Ok(None);
+    }
+
+    let page_size_exp = args.number("--page-size-exp")?.unwrap_or(12);
+    if !(1..32).contains(&page_size_exp) {
+        return Err(format!("--page-size-exp has to be 1 to 31, not {}", page_size_exp));
+    }
+
+    let mapping = match args.flags.get("--mapping").map(String::as_str).unwrap_or("identity") {
+        "identity" => Mapping::Identity,
+        "random" => Mapping::Random { seed: args.number("--seed")?.unwrap_or(0) as u64 },
+        "coloring" => Mapping::PageColoring { colors: vm::page_colors(ui, page_size_exp) },
+        other => return Err(format!("--mapping is identity, random or coloring, not {}", other)),
+    };
+
+    let mut mmu = Mmu::new(page_size_exp, mapping);
+    let levels = args.flags.get("--tlb").map(String::as_str).unwrap_or("");
+    for level in levels.split(',').filter(|level| !level.is_empty()) {
+        let (entries, ways) = level
+            .split_once(':')
+            .and_then(|(entries, ways)| Some((entries.parse::<usize>().ok()?, ways.parse::<usize>().ok()?)))
+            .ok_or(format!("--tlb levels look like entries:ways (e.g. 64:4), not {}", level))?;
+        if entries == 0 || ways == 0 || !entries.is_multiple_of(ways) {
+            return Err(format!("a {} entry TLB can't be {} way set associative", entries, ways));
+        }
+        mmu = mmu.with_tlb(entries, ways);
+    }
+
+    Ok(Some(mmu))
+}
+
+// --protocol msi|mesi|moesi simulates one core per trace file, or --cores N cores sharing one
+// trace with a core id column
+fn run_multi_core(args: &Args, ui: &UserInput, protocol: &str) {
+    let protocol = Protocol::try_from(protocol).unwrap_or_else(|e| exit_with_error(e));
+    let cores = args.number("--cores").unwrap_or_else(|e| exit_with_error(e));
+
+    let open = |file_path: &str| trace::open(file_path).unwrap_or_else(|e| exit_with_error(format!("{}: {}", file_path, e)));
+    // kept out here so we can ask them afterwards whether the traces were ok
+    let mut column_source = None;
+    let mut sources = Vec::new();
+    let (num_cores, source): (usize, Box<dyn coherence::CoreTraceSource + '_>) = match (cores, args.positional.as_slice()) {
+        (Some(0), _) => exit_with_error("--cores has to be at least 1".into()),
+        (Some(cores), [file_path]) => {
+            let source = coherence::trace::CoreColumnSource::open(file_path).unwrap_or_else(|e| exit_with_error(format!("{}: {}", file_path, e)));
+            (cores as usize, Box::new(column_source.insert(source.with_cores(cores as usize))))
+        }
+        (Some(_), _) => exit_with_error("--cores goes with one trace file that has a core id column".into()),
+        (None, []) => exit_with_error("--protocol needs a trace file per core (or --cores and one trace with a core id column)".into()),
+        (None, file_paths) => {
+            sources.extend(file_paths.iter().map(|file_path| open(file_path)));
+            let sources = sources.iter_mut().map(|source| Box::new(source) as Box<dyn TraceSource + '_>).collect();
+            (file_paths.len(), Box::new(coherence::trace::RoundRobinSource::new(sources)))
+        }
+    };
+
+    let mut multi_core = MultiCore::new(ui, num_cores, protocol);
+    // --sharing also sorts coherence misses into true and false sharing
+    if args.switch("--sharing") {
+        multi_core = multi_core.with_sharing_detector();
+    }
+
+    let stats = multi_core.simulate(source);
+    let error = column_source.as_ref().and_then(|source| source.error());
+    if let Some(e) = error.or_else(|| sources.iter().find_map(|source| source.error())) {
+        exit_with_error(e.to_string());
+    }
+    for (core, stats) in stats.iter().enumerate() {
+        println!(
+            "core {}: hits: {}, accesses: {}, coherence misses: {}, invalidations: {}, interventions: {}, bus transactions: {} ({} BusRd, {} BusRdX, {} BusUpgr, {} writebacks)",
+            core, stats.hits, stats.accesses, stats.coherence_misses, stats.invalidations, stats.interventions,
+            stats.bus_transactions(), stats.bus_reads, stats.bus_read_exclusives, stats.bus_upgrades, stats.writebacks
+        );
+    }
+
+    if let Some(sharing) = multi_core.sharing() {
+        println!("true sharing misses: {}, false sharing misses: {}", sharing.true_sharing_misses(), sharing.false_sharing_misses());
+        for line in sharing.worst_lines(10) {
+            println!("line 0x{:08x}: {} false sharing, {} true sharing", line.line_address, line.false_sharing_misses, line.true_sharing_misses);
+            for (core, ranges) in line.touched {
+                let ranges: Vec<_> = ranges.iter().map(|range| format!("{}-{}", range.start, range.end - 1)).collect();
+                println!("    core {} touched bytes {}", core, ranges.join(", "));
+            }
+        }
+    }
+}
+
+// --config file.toml (or .json) simulates the caches in the file from L1 down, on the trace
+// from the command line if there is one and the trace in the file if there isn't
+// (--preset amd-zen3 does the same with a preset's caches)
+fn run_config(args: &Args, config: Config, config_path: &str) {
+    let file_path = args
+        .positional
+        .first()
+        .or(config.trace.as_ref())
+        .unwrap_or_else(|| exit_with_error(format!("{} doesn't have a trace, so give one on the command line", config_path)));
+
+    let mut source = trace::open(file_path).unwrap_or_else(|e| exit_with_error(format!("{}: {}", file_path, e)));
+    // --miss-trace misses.trace writes what the last cache asked memory for as a trace
+    let mut miss_trace = args
+        .flags
+        .get("--miss-trace")
+        .map(|file_path| MissTraceWriter::new(create(file_path).unwrap_or_else(|e| exit_with_error(e))));
+    let results = match &mut miss_trace {
+        Some(miss_trace) => config.simulate_with_memory(&mut source, miss_trace),
+        None => config.simulate(&mut source),
+    };
+    if let Some(e) = source.error() {
+        exit_with_error(e.to_string());
+    }
+
+    for (cache, results) in config.caches.iter().zip(&results) {
+        println!(
+            "{} ({} bytes, {} way): hits: {}, accesses: {}, hit rate: {}, writebacks: {}",
+            cache.name, cache.user_input.cache_size(), cache.user_input.lines_per_set(),
+            results.hits, results.accesses, results.hits as f64 / results.accesses as f64, results.writebacks
+        );
+    }
+    println!("AMAT: {} cycles", config.amat(&results));
+
+    if let Some(miss_trace) = miss_trace {
+        let accesses = miss_trace.accesses;
+        let file_path = &args.flags["--miss-trace"];
+        miss_trace.finish().unwrap_or_else(|e| exit_with_error(format!("{}: {}", file_path, e)));
+        println!("wrote {} misses and writebacks to {}", accesses, file_path);
+    }
+}
+
+// generate <pattern> writes a synthetic trace to --out (or stdout), see generator.rs
+//
+// --accesses, --footprint, --base (hex), --access-size, --store-fraction and --seed work for
+// every pattern, and --stride, --exponent, --node-size, --n and --block are for the patterns
+// that need them
+fn generate(args: &Args) -> Result<(), String> {
+    let name = args.positional.get(1).ok_or("generate needs a pattern: sequential, strided, uniform, zipf, pointer-chase, matmul or blocked-matmul")?;
+
+    // the pattern's own numbers (a bad one just counts as not given, so check them first)
+    for number in ["--stride", "--exponent", "--node-size", "--n", "--block"] {
+        args.float(number)?;
+    }
+    let pattern = Pattern::from_name(name, |number| args.float(&format!("--{}", number)).ok().flatten())?;
+
+    let mut generator = Generator::new(pattern);
+    if let Some(accesses) = args.number("--accesses")? {
+        generator = generator.with_accesses(accesses as usize);
+    }
+    if let Some(footprint) = args.size("--footprint")? {
+        generator = generator.with_footprint(footprint);
+    }
+    if let Some(base) = args.hex("--base")? {
+        generator = generator.with_base(base);
+    }
+    if let Some(access_size) = args.number("--access-size")? {
+        generator = generator.with_access_size(access_size);
+    }
+    if let Some(store_fraction) = args.float("--store-fraction")? {
+        generator = generator.with_store_fraction(store_fraction);
+    }
+    if let Some(seed) = args.number("--seed")? {
+        generator = generator.with_seed(seed as u64);
+    }
+
+    write_trace(args, generator.generate()?)
+}
+
+// a new file to write a trace to
+fn create(file_path: &str) -> Result<BufWriter<File>, String> {
+    File::create(file_path).map(BufWriter::new).map_err(|e| format!("could not create {}: {}", file_path, e))
+}
+
+// write a trace to --out (or stdout) in the same format as trace_files/
+fn write_trace(args: &Args, mut source: impl TraceSource) -> Result<(), String> {
+    let mut out: Box<dyn Write> = match args.flags.get("--out") {
+        Some(file_path) => Box::new(create(file_path)?),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+    while let Some(access) = source.next_access() {
+        writeln!(out, "{}", access).map_err(|e| e.to_string())?;
+    }
+    out.flush().map_err(|e| e.to_string())
+}
+
+// slice, sample, filter and interleave cut traces down (see trace_tools/transform.rs) and
+// write what's left to --out (or stdout), so they can be piped into each other with -
+//
+// slice <trace> --from N --to M               accesses N..M (counting from 0)
+// sample <trace> --every N                    every N-th access
+// sample <trace> --fraction F [--seed S]      each access with chance F
+// filter <trace> [--op l|s] [--min-address 0x...] [--max-address 0x...]
+// interleave <trace> <trace>... [--by round-robin|timestamp] [--quantum N]
+//
+// --by timestamp merges traces that have a time column at the end (see TimedAccess) in time
+// order, round-robin (the default) takes --quantum accesses (1 by default) from each in turn
+fn transform_trace(args: &Args, command: &str) -> Result<(), String> {
+    let traces = &args.positional[1..];
+    let open = |file_path: &String| trace::open(file_path).map_err(|e| format!("{}: {}", file_path, e));
+    if traces.is_empty() {
+        return Err(format!("{} needs a trace (or - for stdin)", command));
+    }
+    if command != "interleave" && traces.len() > 1 {
+        return Err(format!("{} takes one trace, not {}", command, traces.len()));
+    }
+    // lent out below, so we can ask them afterwards whether they were ok
+    let mut sources = traces.iter().map(open).collect::<Result<Vec<_>, _>>()?;
+
+    match command {
+        "slice" => {
+            let from = args.number("--from")?.unwrap_or(0) as usize;
+            let to = args.number("--to")?.map_or(usize::MAX, |to| to as usize);
+            write_trace(args, trace_tools::slice(&mut sources[0], from, to))?;
+        }
+        "sample" => match (args.number("--every")?, args.float("--fraction")?) {
+            (Some(0), None) => return Err("--every has to be at least 1".into()),
+            (Some(n), None) => write_trace(args, trace_tools::sample_every(&mut sources[0], n as usize))?,
+            (None, Some(fraction)) if (0.0..=1.0).contains(&fraction) => {
+                let seed = args.number("--seed")?.unwrap_or(0) as u64;
+                write_trace(args, trace_tools::sample_random(&mut sources[0], fraction, seed))?;
+            }
+            (None, Some(fraction)) => return Err(format!("--fraction has to be between 0 and 1, not {}", fraction)),
+            _ => return Err("sample needs --every or --fraction (not both)".into()),
+        },
+        "filter" => {
+            let kind = args.flags.get("--op").map(|op| AccessKind::try_from(op.as_str())).transpose().map_err(|e| format!("--op: {}", e))?;
+            let min = args.hex("--min-address")?.unwrap_or(0);
+            let max = args.hex("--max-address")?.unwrap_or(u32::MAX);
+            let keep = move |access: &Access| kind.is_none_or(|kind| access.kind == kind) && (min..=max).contains(&access.address);
+            write_trace(args, trace_tools::filter(&mut sources[0], keep))?;
+        }
+        "interleave" => match (args.flags.get("--by").map(String::as_str).unwrap_or("round-robin"), args.number("--quantum")?) {
+            ("round-robin", Some(0)) => return Err("--quantum has to be at least 1".into()),
+            ("round-robin", quantum) => write_trace(args, trace_tools::interleave(sources.iter_mut().collect(), quantum.unwrap_or(1) as usize))?,
+            ("timestamp", Some(_)) => return Err("--quantum is only for --by round-robin".into()),
+            ("timestamp", None) => {
+                let mut sources: Vec<_> = sources.into_iter().map(TimestampSource::new).collect();
+                write_trace(args, trace_tools::interleave_by_time(sources.iter_mut().collect()))?;
+                return match sources.iter().find_map(|source| source.error()) {
+                    Some(e) => Err(e.to_string()),
+                    None => Ok(()),
+                };
+            }
+            (other, _) => return Err(format!("{} is not round-robin or timestamp", other)),
+        },
+        _ => unreachable!("not a trace transform: {}", command),
+    }
+
+    match sources.iter().find_map(|source| source.error()) {
+        Some(e) => Err(e.to_string()),
+        None => Ok(()),
+    }
+}
+
+// trace-stats <trace> describes a trace without simulating it (see trace_tools/stats.rs)
+//
+// --line-size (64 by default) is what counts as a line for unique lines and the footprint,
+// --region-size (1M by default) is how big the address histogram's buckets are and --top (10
+// by default) is how many regions and strides get printed
+fn trace_stats(args: &Args) -> Result<(), String> {
+    let file_path = args.positional.get(1).ok_or("trace-stats needs a trace (or - for stdin)")?;
+    let line_size = args.size("--line-size")?.unwrap_or(64);
+    let region_size = args.size("--region-size")?.unwrap_or(1 << 20);
+    let top = args.number("--top")?.unwrap_or(10) as usize;
+    for (name, size) in [("--line-size", line_size), ("--region-size", region_size)] {
+        if !size.is_power_of_two() {
+            return Err(format!("{} has to be a power of 2, {} isn't", name, size));
+        }
+    }
+
+    let mut source = trace::open(file_path).map_err(|e| format!("{}: {}", file_path, e))?;
+    let stats = TraceStats::of(&mut source, line_size, region_size);
+    if let Some(e) = source.error() {
+        return Err(e.to_string());
+    }
+    let percent = |count: u64| 100.0 * count as f64 / stats.accesses as f64;
+
+    println!("accesses: {}, loads: {} ({:.1}%), stores: {} ({:.1}%), stores per load: {:.3}",
+        stats.accesses, stats.loads, percent(stats.loads), stats.stores, percent(stats.stores), stats.store_load_ratio());
+    println!("unique addresses: {}, unique {} byte lines: {}, footprint: {} bytes",
+        stats.unique_addresses(), line_size, stats.unique_lines(), stats.footprint());
+    if let Some((low, high)) = stats.address_range {
+        println!("addresses: 0x{:08x} to 0x{:08x}", low, high);
+    }
+
+    println!("busiest {} byte regions:", region_size);
+    let mut regions: Vec<_> = stats.regions.iter().collect();
+    regions.sort_by_key(|(region, count)| (std::cmp::Reverse(**count), **region));
+    for (region, count) in regions.into_iter().take(top) {
+        println!("    0x{:08x}: {} ({:.1}%)", region, count, percent(*count));
+    }
+
+    println!("access sizes:");
+    for (size, count) in &stats.sizes {
+        println!("    {} bytes: {} ({:.1}%)", size, count, percent(*count));
+    }
+
+    println!("most common strides:");
+    for (stride, count) in stats.top_strides(top) {
+        println!("    {:+}: {} ({:.1}%)", stride, count, percent(count));
+    }
+    Ok(())
+}
+
+fn print_presets(name: Option<&String>) {
+    match name {
+        None => {
+            for preset in presets::PRESETS {
+                println!("{}: {}", preset.name, preset.description);
+            }
+        }
+        Some(name) => match presets::find(name) {
+            Some(preset) => println!("# {}{}", preset.description, preset.config),
+            None => exit_with_error(format!("there is no preset called {}", name)),
+        },
+    }
+}
+
+// print the error and quit
+fn exit_with_error(msg: String) -> ! {
+    eprintln!("{}", msg);
+    std::process::exit(1);
+}
+
 fn main() {
-    fancy_ascii_art();
+    let args = Args::parse(std::env::args().skip(1)).unwrap_or_else(|e| exit_with_error(e));
+
+    // cargo run -- trace_files/read01.trace, or cargo run -- - to read the trace from stdin
+    let file_path = args.positional.first().map(String::as_str).unwrap_or("trace_files/gcc.trace");
+
+    // `generate zipf --accesses 100000 --out zipf.trace` makes up a trace
+    if args.positional.first().map(String::as_str) == Some("generate") {
+        generate(&args).unwrap_or_else(|e| exit_with_error(e));
+        return;
+    }
+
+    // `trace-stats some.trace` says what's in a trace
+    if args.positional.first().map(String::as_str) == Some("trace-stats") {
+        trace_stats(&args).unwrap_or_else(|e| exit_with_error(e));
+        return;
+    }
+
+    // `slice huge.trace --to 1000000 | filter - --op s` cuts traces down
+    if let Some(command @ ("slice" | "sample" | "filter" | "interleave")) = args.positional.first().map(String::as_str) {
+        transform_trace(&args, command).unwrap_or_else(|e| exit_with_error(e));
+        return;
+    }
+
+    // `presets` lists the built in processors and `presets amd-zen3` prints one as a config file
+    if args.positional.first().map(String::as_str) == Some("presets") {
+        print_presets(args.positional.get(1));
+        return;
+    }
+
+    // configs only simulate one core the correct way, so these would just quietly do nothing
+    if let Some(config_flag) = ["--config", "--preset"].into_iter().find(|flag| args.flags.contains_key(*flag)) {
+        if let Some(switch) = ["--reference", "--sharing"].into_iter().find(|switch| args.switch(switch)) {
+            exit_with_error(format!("{} doesn't work with {}", switch, config_flag));
+        }
+        if args.flags.contains_key("--cores") {
+            exit_with_error(format!("--cores doesn't work with {}", config_flag));
+        }
+    }
 
-    const FILE_PATH: &str = "trace_files/gcc.trace";
+    if let Some(config_path) = args.flags.get("--config") {
+        let config = Config::from_file(config_path).unwrap_or_else(|e| exit_with_error(e));
+        run_config(&args, config, config_path);
+        return;
+    }
+    if let Some(name) = args.flags.get("--preset") {
+        let config = Config::preset(name).unwrap_or_else(|e| exit_with_error(e));
+        run_config(&args, config, name);
+        return;
+    }
 
-    let ui = all_user_input();
-    let mut c = Cache::new(&ui);
-    let sim_results = c.simulate_trace_file(FILE_PATH);
+    // --reference gives the exact same numbers as the cpp simulator (quirks and all)
+    let mode = if args.switch("--reference") {
+        Mode::ReferenceCompatible
+    } else {
+        Mode::Correct
+    };
+
+    // the cpp simulator doesn't have any of these, so they'd just quietly do nothing
+    if mode == Mode::ReferenceCompatible {
+        let flags = ["--prefetch", "--sectors", "--victim-lines", "--index", "--mapping", "--tlb", "--miss-trace", "--protocol"];
+        if let Some(flag) = flags.iter().find(|flag| args.flags.contains_key(**flag)) {
+            exit_with_error(format!("{} doesn't work with --reference", flag));
+        }
+    }
+
+    // the prompts read from stdin too, so a trace on stdin needs the config from flags
+    let ui = match args.user_input().unwrap_or_else(|e| exit_with_error(e)) {
+        Some(ui) => ui,
+        None if file_path == "-" => exit_with_error("reading the trace from stdin needs the config as flags, e.g.\n--cache-size-exp 10 --line-size-exp 4 --type sa --ways-exp 2 --policy l".into()),
+        None => {
+            fancy_ascii_art();
+            all_user_input()
+        }
+    };
+
+    if mode == Mode::ReferenceCompatible && !ui.is_power_of_two() {
+        exit_with_error("--reference needs a power of 2 number of sets and ways (the cpp simulator can't do anything else)".into());
+    }
+    if mode == Mode::ReferenceCompatible && !ui.replacement_policy().in_cpp_simulator() {
+        exit_with_error(format!("--reference needs LRU or FIFO, not {:?} (the cpp simulator doesn't have anything else)", ui.replacement_policy()));
+    }
+
+    if let Some(protocol) = args.flags.get("--protocol") {
+        run_multi_core(&args, &ui, protocol);
+        return;
+    }
+
+    let prefetcher = prefetcher(&args).unwrap_or_else(|e| exit_with_error(e));
+    let prefetching = prefetcher.is_some();
+
+    let latencies = latencies(&args).unwrap_or_else(|e| exit_with_error(e));
+
+    // --energy prints the energy and area estimate, --energy-params file.txt uses other numbers
+    let energy_params = match args.flags.get("--energy-params") {
+        Some(file_path) => Some(EnergyParams::from_file(file_path).unwrap_or_else(|e| exit_with_error(e))),
+        None if args.switch("--energy") => Some(EnergyParams::default()),
+        None => None,
+    };
+    let mmu = mmu(&args, &ui).unwrap_or_else(|e| exit_with_error(e));
+    let translating = mmu.is_some();
+
+    // --sectors 4 splits every line into 4 sectors
+    let sectors = args.number("--sectors").unwrap_or_else(|e| exit_with_error(e));
+    if let Some(sectors) = sectors {
+        if !sectors.is_power_of_two() || sectors > 64 || sectors > ui.line_size() {
+            exit_with_error(format!("--sectors has to be a power of 2, at most 64 and at most the line size ({}), not {}", ui.line_size(), sectors));
+        }
+    }
+
+    // --index xor-fold picks sets with a hash instead of the middle bits of the address
+    let index_function = index_function(&args).unwrap_or_else(|e| exit_with_error(e));
+
+    // --victim-lines 4 puts a 4 line victim cache behind the cache
+    let victim_lines = args.number("--victim-lines").unwrap_or_else(|e| exit_with_error(e));
+    if victim_lines == Some(0) {
+        exit_with_error("--victim-lines has to be at least 1".into());
+    }
+
+    // --miss-trace misses.trace writes the misses and writebacks as a trace (see MissTraceWriter)
+    let mut miss_trace = args
+        .flags
+        .get("--miss-trace")
+        .map(|file_path| MissTraceWriter::new(create(file_path).unwrap_or_else(|e| exit_with_error(e))));
+
+    // --rrpv-bits 3 gives SRRIP, BRRIP and DRRIP 3 bit RRPVs (2 by default)
+    let rrip_flavor = replacement::rrip_flavor(ui.replacement_policy());
+    let rrpv_bits = match (args.number("--rrpv-bits").unwrap_or_else(|e| exit_with_error(e)), rrip_flavor) {
+        (None, _) => 2,
+        (Some(bits @ 1..=8), Some(_)) => bits as u8,
+        (Some(bits), Some(_)) => exit_with_error(format!("--rrpv-bits has to be from 1 to 8, not {}", bits)),
+        (Some(_), None) => exit_with_error(format!("--rrpv-bits is only for srrip, brrip and drrip, not {:?}", ui.replacement_policy())),
+    };
+
+    // DRRIP and DIP get lent to the cache so we can print how the duel went afterwards
+    let is_drrip = rrip_flavor == Some(RripFlavor::Dynamic);
+    let is_dip = ui.replacement_policy() == ReplacementPolicy::DIP;
+    let mut drrip = RripPolicy::drrip(rrpv_bits);
+    let mut dip = AdaptiveInsertion::dip(1.0 / 32.0);
+
+    let sim_results = {
+        let mut c = Cache::with_mode(&ui, mode);
+        match rrip_flavor {
+            Some(RripFlavor::Dynamic) => c.set_replacement_policy(Box::new(&mut drrip)),
+            Some(flavor) => c.set_replacement_policy(Box::new(RripPolicy::new(flavor, rrpv_bits))),
+            None => {}
+        }
+        if is_dip {
+            c.set_replacement_policy(Box::new(LruPolicy::with_insertion(Box::new(&mut dip))));
+        }
+        if let Some(prefetcher) = prefetcher {
+            c.set_prefetcher(prefetcher);
+        }
+        if let Some(mmu) = mmu {
+            c.set_mmu(mmu);
+        }
+        if let Some(sectors) = sectors {
+            c.set_sectors(sectors);
+        }
+        if let Some(victim_lines) = victim_lines {
+            c.set_victim_cache(victim_lines as usize);
+        }
+        c.set_index_function(index_function);
+        if let Some(miss_trace) = &mut miss_trace {
+            c.add_observer(miss_trace);
+        }
+
+        let sim_results = if file_path == "-" {
+            c.simulate_reader(io::stdin().lock(), "stdin")
+        } else {
+            c.simulate_trace_file(file_path)
+        };
+        sim_results.unwrap_or_else(|e| exit_with_error(e.to_string()))
+    };
 
     println!("hits: {}, accesses: {}, hit rate: {}", sim_results.hits, sim_results.accesses, sim_results.hits as f64 / sim_results.accesses as f64);
 
+    if let Some(miss_trace) = miss_trace {
+        let accesses = miss_trace.accesses;
+        let file_path = &args.flags["--miss-trace"];
+        miss_trace.finish().unwrap_or_else(|e| exit_with_error(format!("{}: {}", file_path, e)));
+        println!("wrote {} misses and writebacks to {}", accesses, file_path);
+    }
+
+    let timing = sim_results.timing(&latencies);
+    println!("AMAT: {} cycles, total cycles: {}, stall cycles: {}", timing.amat, timing.total_cycles, timing.stall_cycles);
+
+    if let Some(energy_params) = energy_params {
+        let energy = sim_results.energy(&ui, &energy_params, &latencies);
+        println!(
+            "energy: {:.1} pJ ({:.1} dynamic, {:.1} leakage), EDP: {:.1} pJ*cycles, area: {:.4} mm^2",
+            energy.total_pj, energy.dynamic_pj, energy.leakage_pj, energy.edp, energy.area_mm2
+        );
+    }
+
+    if translating && mode == Mode::Correct {
+        let tlb = &sim_results.tlb;
+        for (level, (hits, misses)) in tlb.hits.iter().zip(&tlb.misses).enumerate() {
+            println!("L{} TLB: {} hits, {} misses", level + 1, hits, misses);
+        }
+        println!("page table walks: {} ({} memory references)", tlb.walks, tlb.walk_references);
+    }
+
+    if sectors.is_some() && mode == Mode::Correct {
+        println!(
+            "sector misses: {}, line misses: {}, bytes fetched: {}, bytes written back: {}",
+            sim_results.sector_misses, sim_results.misses - sim_results.sector_misses, sim_results.bytes_fetched, sim_results.bytes_written_back
+        );
+    }
+
+    if victim_lines.is_some() && mode == Mode::Correct {
+        println!("victim cache hits: {} (out of {} misses)", sim_results.victim_hits, sim_results.misses);
+    }
+
+    if prefetching && mode == Mode::Correct {
+        let stats = sim_results.prefetch;
+        println!(
+            "prefetches: {} issued, {} useful, {} late, {} unused, {} pollution misses",
+            stats.issued, stats.useful, stats.late, stats.unused, stats.pollution
+        );
+    }
+
+    if is_drrip && mode == Mode::Correct {
+        for (i, phase) in drrip.phases().iter().enumerate() {
+            println!(
+                "phase {}: {} accesses, SRRIP leaders missed {}, BRRIP leaders missed {}, winner: {:?}",
+                i, phase.accesses(), phase.leader_misses()[0], phase.leader_misses()[1], phase.winner()
+            );
+        }
+    }
+
+    if is_dip && mode == Mode::Correct {
+        for (i, phase) in dip.phases().iter().enumerate() {
+            println!(
+                "phase {}: {} accesses, LRU insertion leaders missed {}, BIP leaders missed {}, winner: {:?}",
+                i, phase.accesses(), phase.leader_misses()[0], phase.leader_misses()[1], phase.winner()
+            );
+        }
+    }
+
     // example plot
     // this isn't really what the assignment asks for, but is a good example of how to use plotly
     // (if you feel like it)
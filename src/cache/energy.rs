@@ -0,0 +1,241 @@
+use std::fs::read_to_string;
+
+use crate::user_input::UserInput;
+
+use super::{Latencies, SimResults};
+
+// the numbers behind the energy and area model (see SimResults::energy)
+//
+// the defaults are ballpark numbers for a 45nm-ish SRAM and DDR memory. This is a first order
+// model for comparing configs against each other, not CACTI, so load your own numbers with
+// EnergyParams::from_file if you have better ones
+#[derive(Debug, PartialEq, Clone)]
+pub struct EnergyParams {
+    // dynamic energy, in picojoules
+    pub data_read_pj_per_bit: f64,
+    pub data_write_pj_per_bit: f64,
+    pub tag_read_pj_per_bit: f64,
+    pub tag_write_pj_per_bit: f64,
+    // decoding the set index, per index bit
+    pub decoder_pj_per_index_bit: f64,
+    // reading or writing memory (for misses and writebacks)
+    pub memory_pj_per_bit: f64,
+    // leakage, in nanowatts per stored bit (data and tags)
+    pub leakage_nw_per_bit: f64,
+    // to turn cycles into time for leakage
+    pub clock_ghz: f64,
+    // area, in square micrometers
+    pub cell_area_um2: f64,
+    // decoders, sense amps, ... as a fraction of the cell area (0.4 is 40% on top)
+    pub periphery_overhead: f64,
+    // every way has a tag comparator
+    pub comparator_um2_per_bit: f64,
+}
+
+impl Default for EnergyParams {
+    fn default() -> Self {
+        EnergyParams {
+            data_read_pj_per_bit: 0.02,
+            data_write_pj_per_bit: 0.025,
+            tag_read_pj_per_bit: 0.02,
+            tag_write_pj_per_bit: 0.025,
+            decoder_pj_per_index_bit: 0.5,
+            memory_pj_per_bit: 20.0,
+            leakage_nw_per_bit: 2.0,
+            clock_ghz: 2.0,
+            cell_area_um2: 0.35,
+            periphery_overhead: 0.4,
+            comparator_um2_per_bit: 5.0,
+        }
+    }
+}
+
+// a parameter file has one "name = value" per line, with # for comments, e.g.
+//
+// # a slower, leakier process
+// clock_ghz = 1.5
+// leakage_nw_per_bit = 4
+//
+// anything that isn't in the file keeps its default
+impl std::str::FromStr for EnergyParams {
+    type Err = String;
+
+    fn from_str(file: &str) -> Result<Self, Self::Err> {
+        let mut params = EnergyParams::default();
+
+        for (i, line) in file.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (name, value) = line
+                .split_once('=')
+                .ok_or(format!("line {}: expected name = value, got {}", i + 1, line))?;
+            let (name, value) = (name.trim(), value.trim());
+            let value: f64 = value
+                .parse()
+                .map_err(|e| format!("line {}: {} = {} is not a number: {}", i + 1, name, value, e))?;
+
+            let param = match name {
+                "data_read_pj_per_bit" => &mut params.data_read_pj_per_bit,
+                "data_write_pj_per_bit" => &mut params.data_write_pj_per_bit,
+                "tag_read_pj_per_bit" => &mut params.tag_read_pj_per_bit,
+                "tag_write_pj_per_bit" => &mut params.tag_write_pj_per_bit,
+                "decoder_pj_per_index_bit" => &mut params.decoder_pj_per_index_bit,
+                "memory_pj_per_bit" => &mut params.memory_pj_per_bit,
+                "leakage_nw_per_bit" => &mut params.leakage_nw_per_bit,
+                "clock_ghz" => &mut params.clock_ghz,
+                "cell_area_um2" => &mut params.cell_area_um2,
+                "periphery_overhead" => &mut params.periphery_overhead,
+                "comparator_um2_per_bit" => &mut params.comparator_um2_per_bit,
+                _ => return Err(format!("line {}: there is no parameter called {}", i + 1, name)),
+            };
+            *param = value;
+        }
+
+        if params.clock_ghz <= 0.0 {
+            return Err("clock_ghz has to be more than 0".into());
+        }
+        Ok(params)
+    }
+}
+
+impl EnergyParams {
+    pub fn from_file(filepath: &str) -> Result<Self, String> {
+        let file = read_to_string(filepath).map_err(|e| format!("could not read {}: {}", filepath, e))?;
+        file.parse().map_err(|e| format!("{}: {}", filepath, e))
+    }
+}
+
+// how much energy a simulation took and how big the cache is
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Energy {
+    // everything in picojoules
+    pub dynamic_pj: f64,
+    pub leakage_pj: f64,
+    pub total_pj: f64,
+    // energy delay product, total_pj * total cycles
+    pub edp: f64,
+    pub area_mm2: f64,
+}
+
+// how big the arrays are, in bits
+struct Bits {
+    line: f64,
+    // tag plus valid and dirty bits
+    tag: f64,
+    total: f64,
+}
+
+fn bits(user_input: &UserInput) -> Bits {
+    let line = user_input.line_size() as f64 * 8.0;
+    let tag = user_input.tag_size() as f64 + 2.0;
+    let total = user_input.num_lines() as f64 * (line + tag);
+    Bits { line, tag, total }
+}
+
+// the area of a cache, in square millimeters
+pub fn area_mm2(user_input: &UserInput, params: &EnergyParams) -> f64 {
+    let bits = bits(user_input);
+    let cells = bits.total * params.cell_area_um2 * (1.0 + params.periphery_overhead);
+    let comparators = user_input.lines_per_set() as f64 * user_input.tag_size() as f64 * params.comparator_um2_per_bit;
+    (cells + comparators) / 1_000_000.0
+}
+
+impl SimResults {
+    // how much energy this simulation took on a cache configured like user_input
+    //
+    // every access reads the tags and data of every way in its set. Every fill (misses and
+    // prefetches, but not misses the victim cache caught) writes a tag and a line, and every
+    // writeback reads its dirty data back out. Memory is paid for by the byte, for what
+    // bytes_fetched and bytes_written_back say went over the bus (so sectors only pay for the
+    // sectors they move). Leakage runs for as long as SimResults::timing says it took. The
+    // victim cache and TLBs aren't counted.
+    pub fn energy(&self, user_input: &UserInput, params: &EnergyParams, latencies: &Latencies) -> Energy {
+        let bits = bits(user_input);
+        let ways = user_input.lines_per_set() as f64;
+
+        let per_access = ways * (bits.tag * params.tag_read_pj_per_bit + bits.line * params.data_read_pj_per_bit)
+            + user_input.num_sets_exp() as f64 * params.decoder_pj_per_index_bit;
+        let per_fill = bits.tag * params.tag_write_pj_per_bit + bits.line * params.data_write_pj_per_bit;
+
+        let fills = (self.misses - self.victim_hits + self.prefetch.issued) as f64;
+        let (fetched_bits, written_back_bits) = (self.bytes_fetched as f64 * 8.0, self.bytes_written_back as f64 * 8.0);
+        let dynamic_pj = self.accesses as f64 * per_access
+            + fills * per_fill
+            + written_back_bits * params.data_read_pj_per_bit
+            + (fetched_bits + written_back_bits) * params.memory_pj_per_bit;
+
+        // nW * ns is 10^-18 J, which is 10^-6 pJ
+        let cycles = self.timing(latencies).total_cycles as f64;
+        let nanoseconds = cycles / params.clock_ghz;
+        let leakage_pj = bits.total * params.leakage_nw_per_bit * nanoseconds / 1_000_000.0;
+
+        let total_pj = dynamic_pj + leakage_pj;
+        Energy { dynamic_pj, leakage_pj, total_pj, edp: total_pj * cycles, area_mm2: area_mm2(user_input, params) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::user_input::{CacheType, ReplacementPolicy};
+
+    #[test]
+    fn test_params_file() {
+        let params: EnergyParams = "# comment\nclock_ghz = 1.5\n\nmemory_pj_per_bit=10 # cheap memory\n".parse().unwrap();
+        assert_eq!(params, EnergyParams { clock_ghz: 1.5, memory_pj_per_bit: 10.0, ..Default::default() });
+
+        assert_eq!("\nclock_hz = 2".parse::<EnergyParams>(), Err("line 2: there is no parameter called clock_hz".into()));
+        assert!("clock_ghz = fast".parse::<EnergyParams>().is_err());
+    }
+
+    #[test]
+    fn test_more_ways_cost_more() {
+        // same size, direct mapped vs 4 way
+        let direct = UserInput::with_config(10, 4, CacheType::DirectMapped(0), ReplacementPolicy::LRU);
+        let four_way = UserInput::with_config(10, 4, CacheType::SetAssociative(2), ReplacementPolicy::LRU);
+        let results = SimResults { hits: 90, misses: 10, accesses: 100, writebacks: 2, ..Default::default() };
+        let (params, latencies) = (EnergyParams::default(), Latencies::default());
+
+        let direct = results.energy(&direct, &params, &latencies);
+        let four_way = results.energy(&four_way, &params, &latencies);
+        assert!(four_way.dynamic_pj > direct.dynamic_pj);
+        assert!(four_way.area_mm2 > direct.area_mm2);
+        // same number of cycles, but fewer sets means bigger tags to leak
+        assert!(four_way.leakage_pj > direct.leakage_pj);
+        assert_eq!(direct.edp, direct.total_pj * results.timing(&latencies).total_cycles as f64);
+    }
+
+    #[test]
+    fn test_memory_energy_follows_the_bytes() {
+        // 1K, 16 byte lines, 4 sectors of 4 bytes: 6 misses, 2 of them caught by the victim
+        // cache, 1 of the rest a sector miss (3 lines and a sector from memory) and 2 dirty
+        // sectors written back
+        let user_input = UserInput::with_config(10, 4, CacheType::DirectMapped(0), ReplacementPolicy::LRU);
+        let results = SimResults {
+            hits: 4,
+            misses: 6,
+            accesses: 10,
+            victim_hits: 2,
+            sector_misses: 1,
+            writebacks: 1,
+            bytes_fetched: 3 * 16 + 4,
+            bytes_written_back: 2 * 4,
+            ..Default::default()
+        };
+        let params = EnergyParams::default();
+        let energy = results.energy(&user_input, &params, &Latencies::default());
+
+        // the same thing without any misses or traffic is just the reads every access does
+        let reads = SimResults { hits: 10, misses: 0, accesses: 10, ..Default::default() };
+        let reads = reads.energy(&user_input, &params, &Latencies::default());
+
+        // 22 bit tags (plus valid and dirty) and 128 bit lines
+        let fills = 4.0 * (24.0 * params.tag_write_pj_per_bit + 128.0 * params.data_write_pj_per_bit);
+        let writeback_reads = 64.0 * params.data_read_pj_per_bit;
+        let memory = (52.0 + 8.0) * 8.0 * params.memory_pj_per_bit;
+        assert!((energy.dynamic_pj - reads.dynamic_pj - (fills + writeback_reads + memory)).abs() < 1e-6);
+    }
+}
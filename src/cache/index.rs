@@ -0,0 +1,154 @@
+use crate::random::splitmix64;
+use crate::user_input::UserInput;
+
+// how a line picks its set (see Cache::set_index_function)
+//
+// everything but BitSlice uses the whole line number as the tag, since the set can't be worked
+// back out of the hash
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum IndexFunction {
+    // the set bits in the middle of the address (the line number mod the number of sets), like
+    // every textbook cache
+    #[default]
+    BitSlice,
+    // every set index sized chunk of the line number XORed together, so the upper bits pick
+    // the set too and power of 2 strides don't all land in one set
+    XorFold,
+    // the line number mod the biggest prime that's at most the number of sets (Kharbutli et al,
+    // HPCA 2004). The sets past the prime never get used
+    PrimeModulo,
+    // every way hashes the line number differently, so two lines that fight over a set in one
+    // way probably don't in the others (Seznec, ISCA 1993)
+    Skewed,
+    // a keyed hash of the line number (like CEASER), so which lines fight over a set depends on
+    // the key instead of on the addresses
+    Randomized { key: u64 },
+}
+
+impl TryFrom<&str> for IndexFunction {
+    type Error = String;
+
+    // randomized gets key 0 (use IndexFunction::Randomized directly for another one)
+    fn try_from(name: &str) -> Result<Self, Self::Error> {
+        match name.to_lowercase().trim() {
+            "bit-slice" => Ok(IndexFunction::BitSlice),
+            "xor-fold" => Ok(IndexFunction::XorFold),
+            "prime-modulo" => Ok(IndexFunction::PrimeModulo),
+            "skewed" => Ok(IndexFunction::Skewed),
+            "randomized" => Ok(IndexFunction::Randomized { key: 0 }),
+            other => Err(format!("{} is not bit-slice, xor-fold, prime-modulo, skewed or randomized", other)),
+        }
+    }
+}
+
+// an IndexFunction for one cache, with everything it needs worked out ahead of time
+//
+// BitSlice is exactly UserInput::set_and_tag (the same set and tag bits as
+// break_down_binary_address), so it just asks the user input
+#[derive(Debug)]
+pub(crate) struct SetIndex<'a> {
+    function: IndexFunction,
+    context: &'a UserInput,
+    line_size_exp: u32,
+    num_sets: u32,
+    // bits in a set index (for XorFold)
+    index_bits: u32,
+    // the biggest prime <= num_sets (for PrimeModulo, 1 if there's only one set)
+    prime: u32,
+}
+
+impl<'a> SetIndex<'a> {
+    pub(crate) fn new(function: IndexFunction, user_input: &'a UserInput) -> Self {
+        let num_sets = user_input.num_sets();
+        let is_prime = |n: u32| n >= 2 && (2..).take_while(|d| d * d <= n).all(|d| !n.is_multiple_of(d));
+
+        SetIndex {
+            function,
+            context: user_input,
+            line_size_exp: user_input.line_size().ilog2(),
+            num_sets,
+            index_bits: user_input.num_sets_exp(),
+            prime: (2..=num_sets).rev().find(|n| is_prime(*n)).unwrap_or(1),
+        }
+    }
+
+    pub(crate) fn is_skewed(&self) -> bool {
+        self.function == IndexFunction::Skewed
+    }
+
+    // the set an address goes in, in a given way (only Skewed cares about the way)
+    pub(crate) fn set(&self, address: u32, way: u32) -> u32 {
+        let line_number = address >> self.line_size_exp;
+
+        match self.function {
+            IndexFunction::BitSlice => self.context.set_and_tag(address).0,
+            IndexFunction::XorFold => {
+                if self.index_bits == 0 {
+                    return 0;
+                }
+                let mask = (1u64 << self.index_bits) - 1;
+                let (mut rest, mut folded) = (line_number as u64, 0);
+                while rest != 0 {
+                    folded ^= rest & mask;
+                    rest >>= self.index_bits;
+                }
+                (folded % self.num_sets as u64) as u32
+            }
+            IndexFunction::PrimeModulo => line_number % self.prime,
+            // a different constant per way, so every way is its own hash
+            IndexFunction::Skewed => {
+                let salt = (way as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+                (splitmix64(line_number as u64 ^ salt) % self.num_sets as u64) as u32
+            }
+            IndexFunction::Randomized { key } => (splitmix64(line_number as u64 ^ key) % self.num_sets as u64) as u32,
+        }
+    }
+
+    pub(crate) fn tag(&self, address: u32) -> u32 {
+        let line_number = address >> self.line_size_exp;
+        match self.function {
+            IndexFunction::BitSlice => self.context.set_and_tag(address).1,
+            _ => line_number,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::Cache;
+    use crate::user_input::{CacheType, ReplacementPolicy};
+
+    #[test]
+    fn test_sets() {
+        // 8 sets of 16 byte lines
+        let ui = UserInput::with_config(9, 4, CacheType::SetAssociative(2), ReplacementPolicy::LRU);
+
+        // line 0x29 is 101 001 in binary, so 5 ^ 1
+        let xor = SetIndex::new(IndexFunction::XorFold, &ui);
+        assert_eq!((xor.set(0x290, 0), xor.tag(0x290)), (4, 0x29));
+        assert_eq!(SetIndex::new(IndexFunction::PrimeModulo, &ui).set(0x290, 0), 41 % 7);
+        assert_eq!(SetIndex::new(IndexFunction::BitSlice, &ui).tag(0x290), 5);
+
+        // the ways of a skewed cache don't all agree
+        let skewed = SetIndex::new(IndexFunction::Skewed, &ui);
+        let sets: Vec<_> = (0..4).map(|way| skewed.set(0x290, way)).collect();
+        assert!(sets.iter().any(|set| *set != sets[0]), "{:?}", sets);
+    }
+
+    #[test]
+    fn test_hashing_beats_power_of_2_strides() {
+        // 8 sets of 2 ways, and 4 lines 128 bytes apart: bit slicing puts them all in set 0
+        let ui = UserInput::with_config(8, 4, CacheType::SetAssociative(1), ReplacementPolicy::LRU);
+        let trace: String = (0..10).flat_map(|_| (0..4).map(|i| format!("l 0x{:08x} 1\n", i * 128))).collect();
+
+        let hits = |function| {
+            let mut cache = Cache::new(&ui);
+            cache.set_index_function(function);
+            cache.simulate_trace(&trace).hits
+        };
+        assert_eq!(hits(IndexFunction::BitSlice), 0);
+        assert_eq!(hits(IndexFunction::XorFold), 36);
+        assert_eq!(hits(IndexFunction::PrimeModulo), 36);
+    }
+}
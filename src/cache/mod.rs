@@ -1,7 +1,33 @@
-use std::collections::HashMap;
-use std::fs::read_to_string;
+mod energy;
+mod index;
+mod observer;
+pub mod prefetch;
+mod reference;
+pub mod replacement;
+mod timing;
+pub mod trace;
+mod victim;
 
-use crate::user_input::{self, UserInput};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+
+use crate::user_input::UserInput;
+use crate::vm::{Mmu, TlbStats};
+
+use index::SetIndex;
+use observer::Observers;
+use prefetch::DemandAccess;
+use reference::ReferenceCache;
+use victim::VictimCache;
+
+pub use energy::{area_mm2, Energy, EnergyParams};
+pub use index::IndexFunction;
+pub use observer::{writeback_stores, CacheObserver, MissStream, MissTraceWriter};
+pub use prefetch::{PrefetchStats, Prefetcher};
+pub use replacement::ReplacementPolicy;
+pub use timing::{hierarchy_amat, Latencies, Timing};
+pub use trace::{Access, TraceSource};
 
 // take a hex character and convert it to binary!
 //
@@ -34,236 +60,756 @@ fn hex_str_to_binary_str(hex_str: &str) -> String {
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Line {
     pub addy: String, // full address of data in memory that the line represents
-    pub last_access: std::time::Instant,
-    pub birthday: std::time::Instant, // when the line was created
+    pub way: u32, // which line in the set this is (the replacement policy only knows lines by way)
     pub num_accesses: u32,
+    pub dirty: bool, // has it been stored to since it was loaded?
+    // one bit per sector (bit 0 is the first sector), see Cache::set_sectors
+    // (without sectors the whole line is sector 0, so these are just 1 and dirty)
+    pub valid_sectors: u64,
+    pub dirty_sectors: u64,
+}
+
+// is an access a load or a store?
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum AccessKind {
+    Load,
+    Store,
+}
+
+// "l" is a load and "s" is a store (like in the trace files)
+impl TryFrom<&str> for AccessKind {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.to_lowercase().trim() {
+            "l" => Ok(AccessKind::Load),
+            "s" => Ok(AccessKind::Store),
+            _ => Err(format!("we should only get l or s. Got {}", value)),
+        }
+    }
+}
+
+// what happened during one access (see Cache::access)
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct AccessOutcome {
+    pub hit: bool,
+    pub set: u32, // the set index (0 when there's only one set)
+    pub tag: u32,
+    pub evicted: Option<Eviction>, // the line that got kicked out to make room (if any)
+    // it missed, but only because the sector wasn't there (the line was, see Cache::set_sectors)
+    pub sector_miss: bool,
+    // it missed, but the line was in the victim cache (see Cache::set_victim_cache)
+    pub victim_hit: bool,
+}
+
+// a line that got kicked out of the cache
+//
+// with a victim cache it goes there instead of leaving, so dirty doesn't mean it got written
+// back yet
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Eviction {
+    pub address: u32, // line address (offset bits are 0)
+    pub dirty: bool,  // if it's dirty it had to be written back
+}
+
+// which rules does the simulation follow?
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Mode {
+    // what a real cache would do
+    #[default]
+    Correct,
+    // exactly what default_cache_sim.cpp does, quirks and all (see reference.rs)
+    //
+    // use this if you need the same numbers as the cpp simulator (e.g. for grading)
+    ReferenceCompatible,
 }
 
 #[derive(Debug)]
 pub struct Cache<'a> {
     // this big hash map type is very scary (and slow)
     // think of it like this:
-    // we use a set_id (binary string, eg "0101") to get to a Hashmap that contains all the lines
+    // we use a set index (eg 5) to get to a Hashmap that contains all the lines
     // contained by that set.
     // We then use a tag to get the line from the set
     //
-    // TLDR set_id gets us to the set
+    // TLDR set index gets us to the set
     // and then tag gets us to the line
     //
-    // in the case where there are no sets, the set_id is the empty string "".
+    // (both come from the set index function, see index.rs, so the number of sets doesn't have
+    // to be a power of 2)
+    //
+    // in the case where there are no sets, everything is in set 0.
     // This has no effect on simulation results
     //
-    cache: HashMap<String, HashMap<String, Line>>,
+    cache: HashMap<u32, HashMap<u32, Line>>,
     context: &'a UserInput,
+    // turns addresses into sets and tags
+    index: SetIndex<'a>,
+    // picks which line to kick out when a set is full
+    replacement_policy: Box<dyn ReplacementPolicy + 'a>,
+    observers: Observers<'a>,
+    // only used in Mode::ReferenceCompatible, where it does all the simulating
+    reference: Option<ReferenceCache>,
+    // everything below is for prefetching (see prefetch.rs)
+    prefetcher: Option<Box<dyn Prefetcher + 'a>>,
+    // how many accesses it takes for a prefetched line to arrive
+    prefetch_latency: u64,
+    // number of demand accesses so far (the clock for prefetch_latency)
+    demand_accesses: u64,
+    // prefetched lines that haven't been used yet, and when they were prefetched
+    prefetched: HashMap<u32, u64>,
+    // lines that got kicked out to make room for a prefetch (for PrefetchStats::pollution)
+    evicted_by_prefetch: HashSet<u32>,
+    prefetch_stats: PrefetchStats,
+    // catches lines kicked out of this cache (see victim.rs)
+    victim_cache: Option<VictimCache>,
+    // translates virtual addresses to physical ones before they get here (see vm)
+    mmu: Option<Mmu>,
+    // dirty lines written back to memory so far
+    writebacks: u32,
+    // sectors per line (1 means lines aren't sectored)
+    sectors: u32,
+    // bytes loaded from and written back to memory so far
+    bytes_fetched: u64,
+    bytes_written_back: u64,
 }
 
 impl<'a> Cache<'a> {
     pub fn new(user_input: &'a UserInput) -> Self {
+        Cache::with_mode(user_input, Mode::Correct)
+    }
+
+    pub fn with_mode(user_input: &'a UserInput, mode: Mode) -> Self {
+        let reference = match mode {
+            Mode::Correct => None,
+            Mode::ReferenceCompatible => {
+                assert!(user_input.is_power_of_two(), "the cpp simulator only does powers of 2 sets and ways");
+                let policy = user_input.replacement_policy();
+                assert!(policy.in_cpp_simulator(), "the cpp simulator only does LRU and FIFO, not {:?}", policy);
+                Some(ReferenceCache::new(user_input))
+            }
+        };
+
+        let mut replacement_policy = replacement::from_user_input(user_input.replacement_policy());
+        replacement_policy.init(user_input.num_sets(), user_input.lines_per_set());
+
         Cache {
             cache: HashMap::new(),
             context: user_input,
+            index: SetIndex::new(IndexFunction::BitSlice, user_input),
+            replacement_policy,
+            observers: Observers::default(),
+            reference,
+            prefetcher: None,
+            prefetch_latency: 0,
+            demand_accesses: 0,
+            prefetched: HashMap::new(),
+            evicted_by_prefetch: HashSet::new(),
+            prefetch_stats: PrefetchStats::default(),
+            victim_cache: None,
+            mmu: None,
+            writebacks: 0,
+            sectors: 1,
+            bytes_fetched: 0,
+            bytes_written_back: 0,
         }
     }
 
-    // does the cache contain a certain binary address?
-    fn contains(&self, binary_address: &String) -> bool {
-        let (tag, set, _offset) = self.context.break_down_binary_address(binary_address);
+    // pick sets with something other than the middle bits of the address (see index.rs)
+    //
+    // this empties the cache (and the victim cache) too (does nothing in Mode::ReferenceCompatible)
+    pub fn set_index_function(&mut self, function: IndexFunction) {
+        self.index = SetIndex::new(function, self.context);
+        self.empty();
+    }
 
-        let set_found = self.cache.get(set).is_some();
-        if !set_found {
-            return false;
+    // use some other replacement policy than the one in the user input
+    //
+    // this empties the cache, so do it before simulating anything
+    // (does nothing in Mode::ReferenceCompatible)
+    pub fn set_replacement_policy(&mut self, policy: Box<dyn ReplacementPolicy + 'a>) {
+        self.replacement_policy = policy;
+        self.empty();
+    }
+
+    // throw away every line (the victim cache's too), for the setters that change where lines go
+    // or what the policy knows about them
+    fn empty(&mut self) {
+        self.cache.clear();
+        self.replacement_policy.init(self.context.num_sets(), self.context.lines_per_set());
+        self.prefetched.clear();
+        self.evicted_by_prefetch.clear();
+        if let Some(victim_cache) = &mut self.victim_cache {
+            victim_cache.clear();
         }
+    }
+
+    // load lines before they are asked for (see prefetch.rs), there is no prefetcher by default
+    //
+    // this empties the cache (and the victim cache) too (does nothing in Mode::ReferenceCompatible)
+    pub fn set_prefetcher(&mut self, mut prefetcher: Box<dyn Prefetcher + 'a>) {
+        prefetcher.init(self.context.line_size());
+        self.prefetcher = Some(prefetcher);
+        self.empty();
+    }
+
+    // put a fully associative victim cache with this many lines behind the cache
+    //
+    // misses that find their line in it count as victim hits (AccessOutcome::victim_hit and
+    // SimResults::victim_hits) but are still misses. This empties the cache too (does nothing
+    // in Mode::ReferenceCompatible)
+    pub fn set_victim_cache(&mut self, lines: usize) {
+        self.victim_cache = Some(VictimCache::new(lines));
+        self.empty();
+    }
+
+    // treat addresses as virtual and translate them with an Mmu (TLBs and a page table, see
+    // vm) first, so the cache is physically indexed and tagged
+    //
+    // this empties the cache (and the victim cache) too (does nothing in Mode::ReferenceCompatible)
+    pub fn set_mmu(&mut self, mmu: Mmu) {
+        self.mmu = Some(mmu);
+        self.empty();
+    }
+
+    pub fn mmu(&self) -> Option<&Mmu> {
+        self.mmu.as_ref()
+    }
+
+    // split every line into this many sectors (a power of 2, at most 64 and at most one per
+    // byte), each with its own valid and dirty bit
+    //
+    // there's still one tag per line, but a miss only loads the sector it touched, and only
+    // dirty sectors get written back. A miss on a line that's there but without the right
+    // sector is a sector miss (SimResults::sector_misses): it loads the sector without kicking
+    // anything out, and observers only get on_miss and on_fetch for it. Prefetches load whole lines.
+    //
+    // this empties the cache (and the victim cache) too (does nothing in Mode::ReferenceCompatible)
+    pub fn set_sectors(&mut self, sectors: u32) {
+        assert!(sectors.is_power_of_two() && sectors <= 64, "sectors per line has to be a power of 2 up to 64");
+        assert!(sectors <= self.context.line_size(), "can't have more sectors than bytes in a line");
+
+        self.sectors = sectors;
+        self.empty();
+    }
+
+    fn sector_size(&self) -> u64 {
+        (self.context.line_size() / self.sectors) as u64
+    }
+
+    // a mask with a bit for every sector
+    fn all_sectors(&self) -> u64 {
+        u64::MAX >> (64 - self.sectors)
+    }
 
-        let line_found = self.cache.get(set).unwrap().get(tag).is_some();
-        return line_found;
+    // the bit for the sector an address is in
+    fn sector_bit(&self, binary_address: &str) -> u64 {
+        let address = u32::from_str_radix(binary_address, 2).unwrap();
+        let offset = address & (self.context.line_size() - 1);
+        1 << (offset / (self.context.line_size() / self.sectors))
     }
 
-    // is there empty space in the provided set?
+    // how many accesses after being prefetched a line shows up (0 by default)
     //
-    // set to check must be in cache or we panic
-    fn empty_space(&self, set: &str) -> bool {
-        let lines_in_set = self.cache.get(set).expect("set must exist").len() as u32;
-        let lines_per_set = self.context.lines_per_set();
-        assert!(lines_in_set <= lines_per_set);
-        return lines_in_set < lines_per_set;
+    // the simulator doesn't have timing, so the line is in the cache right away no matter what.
+    // Using it sooner than this just counts as a late prefetch (PrefetchStats::late)
+    pub fn set_prefetch_latency(&mut self, accesses: u64) {
+        self.prefetch_latency = accesses;
+    }
+
+    // get told about hits, misses, fills, evictions and writebacks (see observer.rs)
+    pub fn add_observer(&mut self, observer: &'a mut dyn CacheObserver) {
+        self.observers.0.push(observer);
+    }
+
+    // the address of the line that a binary address lives in, as a number
+    fn line_address(&self, binary_address: &str) -> u32 {
+        let address = u32::from_str_radix(binary_address, 2).unwrap();
+        address & !(self.context.line_size() - 1)
+    }
+
+    // the set (in way 0, see IndexFunction::Skewed) and tag of a binary address
+    fn set_and_tag(&self, binary_address: &str) -> (u32, u32) {
+        let address = u32::from_str_radix(binary_address, 2).unwrap();
+        (self.index.set(address, 0), self.index.tag(address))
+    }
+
+    // every (set, way) a binary address could be in (all in the same set unless indexing is
+    // skewed)
+    fn candidates(&self, binary_address: &str) -> Vec<(u32, u32)> {
+        let address = u32::from_str_radix(binary_address, 2).unwrap();
+        (0..self.context.lines_per_set()).map(|way| (self.index.set(address, way), way)).collect()
+    }
+
+    // the set a binary address is in, if it's in the cache
+    fn find(&self, binary_address: &str) -> Option<u32> {
+        let (set, tag) = self.set_and_tag(binary_address);
+        if !self.index.is_skewed() {
+            return self.cache.get(&set)?.get(&tag).map(|_| set);
+        }
+
+        // in a skewed cache it has to be in the right set for its way
+        self.candidates(binary_address)
+            .into_iter()
+            .find(|(set, way)| self.cache.get(set).and_then(|lines| lines.get(&tag)).is_some_and(|line| line.way == *way))
+            .map(|(set, _)| set)
+    }
+
+    pub fn mode(&self) -> Mode {
+        match self.reference {
+            Some(_) => Mode::ReferenceCompatible,
+            None => Mode::Correct,
+        }
+    }
+
+    // does the cache contain a certain binary address?
+    fn contains(&self, binary_address: &str) -> bool {
+        self.find(binary_address).is_some()
     }
 
     // write to the cache
     //
-    // write and read are the same thing :(
+    // write and read are the same thing :( (except stores make the line dirty)
     //
-    // if the binary_address is alrady in the cache, return true (we got a hit)
+    // if the binary_address is alrady in the cache, we got a hit
     //
     // otherwise load the address into the cache
-    // (according to cache type and replacement option)
-    fn write(&mut self, binary_address: &String) -> bool {
+    // (according to cache type and replacement policy)
+    fn write(&mut self, binary_address: &str, kind: AccessKind) -> AccessOutcome {
         // break address down into set, tag, offset
-        let (tag, set, _) = self.context.break_down_binary_address(binary_address);
+        let (mut set_index, tag) = self.set_and_tag(binary_address);
+        let address = self.line_address(binary_address);
+        let mut outcome = AccessOutcome {
+            hit: false,
+            set: set_index,
+            tag,
+            evicted: None,
+            sector_miss: false,
+            victim_hit: false,
+        };
+        let sector = self.sector_bit(binary_address);
+        let dirty_sector = if kind == AccessKind::Store { sector } else { 0 };
 
         // does it already exist? if yes we are done
-        if self.contains(binary_address) {
-            // update last accessed feild
-            self.cache
-                .get_mut(set)
-                .unwrap()
-                .get_mut(tag)
-                .unwrap()
-                .last_access = std::time::Instant::now();
-            self.cache
-                .get_mut(set)
-                .unwrap()
-                .get_mut(tag)
-                .unwrap()
-                .num_accesses += 1;
+        if let Some(set) = self.find(binary_address) {
+            set_index = set;
+            outcome.set = set;
+            let line = self.cache.get_mut(&set_index).unwrap().get_mut(&tag).unwrap();
+            line.num_accesses += 1;
+            line.dirty |= kind == AccessKind::Store;
+            let sector_hit = line.valid_sectors & sector != 0;
+            line.valid_sectors |= sector;
+            line.dirty_sectors |= dirty_sector;
+            self.replacement_policy.on_hit(set_index, line.way);
+            self.replacement_policy.on_access(set_index, !sector_hit);
+
+            // the line is here but the sector isn't, so just load the sector
+            if !sector_hit {
+                self.observers.notify(|o| o.on_miss(address, kind));
+                self.fetch(address, sector);
+                outcome.sector_miss = true;
+                return outcome;
+            }
 
-            return true;
+            self.observers.notify(|o| o.on_hit(address, kind));
+            if let Some(prefetched_at) = self.prefetched.remove(&address) {
+                self.prefetch_stats.useful += 1;
+                if self.demand_accesses - prefetched_at < self.prefetch_latency {
+                    self.prefetch_stats.late += 1;
+                }
+            }
+            outcome.hit = true;
+            return outcome;
         }
 
-        // does the set exist yet? If not create it as empty
-        // (this is more an implementation detail and has nothing to do with the simulation)
-        let set_already_exists = self.cache.get(set).is_some();
-        if !set_already_exists {
-            self.cache.insert(set.into(), HashMap::new());
-            // sanity check (making sure we don't have too many sets)
-            assert!(self.cache.len() <= 2usize.pow(set.len() as u32))
+        self.observers.notify(|o| o.on_miss(address, kind));
+        if self.evicted_by_prefetch.contains(&address) {
+            self.prefetch_stats.pollution += 1;
         }
 
-        // is there an empty space? if so, Insert there
-        if self.empty_space(set) {
-            self.cache.get_mut(set).unwrap().insert(
-                tag.into(),
-                Line {
-                    addy: binary_address.clone(),
-                    last_access: std::time::Instant::now(),
-                    birthday: std::time::Instant::now(),
-                    num_accesses: 0,
-                },
-            );
-            return false;
+        // a line from the victim cache swaps places with whatever gets kicked out to make room
+        let from_victim_cache = self.victim_cache.as_mut().and_then(|victim_cache| victim_cache.take(address));
+        outcome.victim_hit = from_victim_cache.is_some();
+
+        let (valid_sectors, dirty_sectors) = from_victim_cache.unwrap_or((0, 0));
+        if valid_sectors & sector == 0 {
+            self.fetch(address, sector);
         }
+        let (set, evicted) = self.fill(binary_address, valid_sectors | sector, dirty_sectors | dirty_sector, false);
+        self.replacement_policy.on_access(set, true);
+        outcome.set = set;
+        outcome.evicted = evicted;
+        outcome
+    }
+
+    // read a sector from the next level down (the whole line without sectors), address is the
+    // line's
+    fn fetch(&mut self, address: u32, sector: u64) {
+        let size = self.sector_size();
+        let sector_address = address + sector.trailing_zeros() * size as u32;
+        self.bytes_fetched += size;
+        self.observers.notify(|o| o.on_fetch(sector_address, size as u32));
+    }
 
-        // determine which line to replace (which takes the replacement_policy into account)
-        let tag_to_replace: String = match self.context.replacement_policy() {
-            user_input::ReplacementPolicy::LRU => {
-                let tag_of_line_to_replace = self
-                    .cache
-                    .get(set)
-                    .unwrap()
-                    .into_iter()
-                    .min_by_key(|(_tag, line)| line.last_access)
-                    .unwrap()
-                    .0;
-                tag_of_line_to_replace.into()
+    // load a line that isn't in the cache yet (kicking something out if the set is full)
+    //
+    // the sectors are what's in the new line (see Line), prefetch is for lines the prefetcher
+    // asked for
+    //
+    // returns the set the line went in and what got kicked out
+    fn fill(&mut self, binary_address: &str, valid_sectors: u64, dirty_sectors: u64, prefetch: bool) -> (u32, Option<Eviction>) {
+        let (_, tag) = self.set_and_tag(binary_address);
+        let address = self.line_address(binary_address);
+        let candidates = self.candidates(binary_address);
+        let mut evicted = None;
+
+        // do the sets exist yet? If not create them as empty
+        // (this is more an implementation detail and has nothing to do with the simulation)
+        for (set, _) in &candidates {
+            self.cache.entry(*set).or_default();
+        }
+        // sanity check (making sure we don't have too many sets)
+        assert!(self.cache.len() <= self.context.num_sets() as usize);
+
+        // the lowest empty way (lines only leave without being replaced when they get
+        // invalidated, so this is usually just the number of lines in the set)
+        let empty = candidates
+            .iter()
+            .find(|(set, way)| self.cache[set].values().all(|line| line.way != *way))
+            .copied();
+
+        let (set, way) = if let Some(empty) = empty {
+            empty
+        } else {
+            // determine which line to replace (the replacement policy's call)
+            let (set, way) = if self.index.is_skewed() {
+                let way = self.replacement_policy.choose_skewed_victim(&candidates);
+                candidates[way as usize]
+            } else {
+                let set = candidates[0].0;
+                (set, self.replacement_policy.choose_victim(set))
+            };
+            let tag_to_replace = self
+                .cache
+                .get(&set)
+                .unwrap()
+                .iter()
+                .find(|(_tag, line)| line.way == way)
+                .expect("the replacement policy picked a way that doesn't exist")
+                .0
+                .to_owned();
+
+            // make the replacement
+            // (more like remove old and add new)
+            let victim = self
+                .cache
+                .get_mut(&set)
+                .unwrap()
+                .remove(&tag_to_replace)
+                .expect("cant remove something that is not there");
+
+            let victim_address = self.line_address(&victim.addy);
+            self.observers.notify(|o| o.on_eviction(victim_address, victim.dirty));
+
+            // with a victim cache, the line that gets written back (if any) is the one that
+            // leaves the victim cache to make room
+            let leaving = match &mut self.victim_cache {
+                Some(victim_cache) => victim_cache.insert(victim_address, victim.valid_sectors, victim.dirty_sectors),
+                None => Some((victim_address, victim.valid_sectors, victim.dirty_sectors)),
+            };
+            if let Some((leaving_address, _, dirty_sectors)) = leaving.filter(|(_, _, dirty_sectors)| *dirty_sectors != 0) {
+                self.writebacks += 1;
+                let sector_size = self.sector_size();
+                self.bytes_written_back += dirty_sectors.count_ones() as u64 * sector_size;
+                self.observers.notify(|o| o.on_writeback(leaving_address, dirty_sectors, sector_size as u32));
+            }
+
+            if self.prefetched.remove(&victim_address).is_some() {
+                self.prefetch_stats.unused += 1;
             }
-            user_input::ReplacementPolicy::FIFO => {
-                let tag_of_line_to_replace = self
-                    .cache
-                    .get(set)
-                    .unwrap()
-                    .into_iter()
-                    .min_by_key(|(_tag, line)| line.birthday)
-                    .unwrap()
-                    .0;
-                tag_of_line_to_replace.into()
+            if prefetch {
+                self.evicted_by_prefetch.insert(victim_address);
             }
+
+            evicted = Some(Eviction { address: victim_address, dirty: victim.dirty });
+            (set, way)
         };
 
-        // make the replacement
-        // (more like remove old and add new)
-        self.cache
-            .get_mut(set)
-            .unwrap()
-            .remove(&tag_to_replace)
-            .expect("cant remove something that is not there");
-        self.cache.get_mut(set).unwrap().insert(
-            tag.into(),
+        self.cache.get_mut(&set).unwrap().insert(
+            tag,
             Line {
-                addy: binary_address.into(),
-                last_access: std::time::Instant::now(),
-                birthday: std::time::Instant::now(),
+                addy: binary_address.to_string(),
+                way,
                 num_accesses: 0,
+                dirty: dirty_sectors != 0,
+                valid_sectors,
+                dirty_sectors,
             },
         );
-        return false;
-    }
-
-    pub fn simulate_trace_file(&mut self, filepath: &str) -> SimResults {
-        let mut counter = 0;
-        let mut hits = 0;
-
-        // these two vectors make this whole thing so slow :(
-        let mut accesses_history = Vec::new();
-        let mut hit_history = Vec::new();
-
-        // iterate over lines in a file
-        for line in read_to_string(filepath)
-            .expect(&format!("{} is not a valid file path", filepath))
-            .lines()
-        {
-            let line = line.trim().to_lowercase();
-            counter += 1; // just counting the number of lines in the file
-
-            // slow stuff for the chart example in main
-            hit_history.push(hits);
-            accesses_history.push(counter);
-
-            // get ls and address from the line
-            let mut line_iter = line.split(' ').take(2);
-            let ls = line_iter.next().unwrap();
-            let hex_addy = line_iter
-                .next()
-                .unwrap()
-                .strip_prefix("0x")
-                .expect("hex addresses should start with 0x");
-            let binary_addy = hex_str_to_binary_str(hex_addy);
+        self.replacement_policy.on_fill(set, way);
+        self.observers.notify(|o| o.on_fill(address));
 
+        self.evicted_by_prefetch.remove(&address);
+        if prefetch {
+            self.prefetched.insert(address, self.demand_accesses);
+            self.prefetch_stats.issued += 1;
+        }
 
-            // loads and stores handled the same according to the old simulator
-            // (seems weird to me)
-            if ls == "l" || ls == "s" {
-                if self.write(&binary_addy) {
-                    hits += 1;
-                }
-            } else {
-                unreachable!("we should only get l or s. Got {}", ls);
+        (set, evicted)
+    }
+
+    // ask the prefetcher what to load after a demand access, and load it
+    fn prefetch(&mut self, access: &DemandAccess) {
+        let Some(prefetcher) = &mut self.prefetcher else {
+            return;
+        };
+
+        for line_address in prefetcher.on_access(access) {
+            let binary_address = format!("{:032b}", line_address);
+            // prefetching something that's already there does nothing
+            let in_victim_cache = self.victim_cache.as_ref().is_some_and(|victim_cache| victim_cache.contains(line_address));
+            if !self.contains(&binary_address) && !in_victim_cache {
+                let line_size = self.context.line_size();
+                self.bytes_fetched += line_size as u64;
+                self.observers.notify(|o| o.on_fetch(line_address, line_size));
+                self.fill(&binary_address, self.all_sectors(), 0, true);
             }
         }
+    }
+
+    // throw out the line an address lives in without writing it back (another core wrote to
+    // it, see coherence), returns whether it was there
+    pub(crate) fn invalidate(&mut self, address: u32) -> bool {
+        let binary_address = format!("{:032b}", address);
+        let Some(set) = self.find(&binary_address) else {
+            return false;
+        };
+        let (_, tag) = self.set_and_tag(&binary_address);
+        let line_address = self.line_address(&binary_address);
+        self.prefetched.remove(&line_address);
+
+        self.cache.get_mut(&set).unwrap().remove(&tag).is_some()
+    }
+
+    // load or store one address and find out what happened
+    //
+    // this is all simulate_trace_file does for every line, so use it if your addresses don't
+    // come from a file
+    //
+    // in Mode::ReferenceCompatible the cpp simulator doesn't know what it evicted, so evicted
+    // is always None
+    //
+    // with an Mmu, address is virtual and everything in the outcome is physical
+    pub fn access(&mut self, address: u32, kind: AccessKind) -> AccessOutcome {
+        let binary_addy = format!("{:032b}", address);
+
+        if let Some(reference) = &mut self.reference {
+            let hit = reference.access(&format!("{:08x}", address));
+            let (set, tag) = self.context.set_and_tag(address);
+            return AccessOutcome {
+                hit,
+                set,
+                tag,
+                evicted: None,
+                sector_miss: false,
+                victim_hit: false,
+            };
+        }
+
+        // everything from here on uses the physical address
+        let binary_addy = match &mut self.mmu {
+            // there's a frame for every page, so they can't run out
+            Some(mmu) => format!("{:032b}", mmu.translate(address).expect("every page has a frame")),
+            None => binary_addy,
+        };
+
+        self.demand_accesses += 1;
+        let line_address = self.line_address(&binary_addy);
+        let first_use_of_prefetch = self.prefetched.contains_key(&line_address);
+
+        let outcome = self.write(&binary_addy, kind);
+        self.prefetch(&DemandAccess { line_address, kind, hit: outcome.hit, first_use_of_prefetch });
+        outcome
+    }
+
+    // run every access from a trace source through the cache
+    //
+    // e.g. cache.simulate(trace::VecSource::from(accesses)) or cache.simulate(trace::open("-")?)
+    pub fn simulate(&mut self, mut source: impl TraceSource) -> SimResults {
+        let mut simulation = Simulation::start(self);
+        while let Some(access) = source.next_access() {
+            simulation.access(self, access);
+        }
+        simulation.finish(self)
+    }
+
+    // run a whole trace file through the cache (errors say which file, and which line if it
+    // didn't follow the trace format)
+    pub fn simulate_trace_file(&mut self, filepath: &str) -> io::Result<SimResults> {
+        let file = File::open(filepath).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", filepath, e)))?;
+        self.simulate_reader(BufReader::new(file), filepath)
+    }
+
+    // run a whole trace file from a reader through the cache, e.g.
+    // cache.simulate_reader(io::stdin().lock(), "stdin")
+    //
+    // the cpp simulator has some quirks with how it reads files (see ReferenceCache::simulate_trace),
+    // so in Mode::ReferenceCompatible this reads the whole thing first
+    pub fn simulate_reader(&mut self, mut reader: impl BufRead, name: &str) -> io::Result<SimResults> {
+        if let Some(reference) = &mut self.reference {
+            let mut file = String::new();
+            reader.read_to_string(&mut file).map_err(|e| io::Error::new(e.kind(), format!("could not read {}: {}", name, e)))?;
+            return Ok(reference.simulate_trace(&file));
+        }
+
+        let mut source = trace::ReaderSource::new(reader, name);
+        let results = self.simulate(&mut source);
+        match source.error() {
+            Some(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+            None => Ok(results),
+        }
+    }
 
-        /*
-         * we do not need this and it is painfully slow
-        let mut cloned_map = HashMap::new();
-
-        self.cache.iter().for_each(|(set_id, lines)| {
-            let mut set = HashMap::new();
-            lines.iter().for_each(|(tag_id, line)| {
-                set.insert(tag_id.clone(), line.clone());
-            });
-            cloned_map.insert(set_id.clone(), set);
-        });
-        */
-
-        SimResults {
-            // final_cache: cloned_map,
-            hits,
-            accesses: counter,
-            hit_history,
-            accesses_history,
+    // same as simulate_trace_file, but with the contents of the trace file instead of its path
+    #[cfg(test)]
+    fn simulate_trace(&mut self, file: &str) -> SimResults {
+        if let Some(reference) = &mut self.reference {
+            return reference.simulate_trace(file);
         }
+
+        self.simulate(trace::ReaderSource::new(file.as_bytes(), "test trace"))
     }
 }
 
-#[derive(Debug)]
+// what happened during a simulation
+//
+// for cycles and AMAT see SimResults::timing (in timing.rs), for energy SimResults::energy (in
+// energy.rs)
+#[derive(Debug, Default)]
 pub struct SimResults {
     // pub final_cache: HashMap<String, HashMap<String, Line>>,
     pub hits: u32,
+    pub misses: u32,
     pub accesses: u32,
+    // misses that found their line in the victim cache (see Cache::set_victim_cache)
+    pub victim_hits: u32,
+    // dirty lines written back to memory (always 0 in Mode::ReferenceCompatible, the cpp
+    // simulator doesn't know about dirty lines)
+    pub writebacks: u32,
+    // misses where the line was there but the sector wasn't (see Cache::set_sectors), the rest
+    // of the misses are line misses
+    pub sector_misses: u32,
+    // the same counts for loads only. A lower level cache gets loads for the misses above it
+    // and stores for the writebacks, so these are its demand accesses (see hierarchy_amat)
+    pub loads: u32,
+    pub load_misses: u32,
+    pub load_victim_hits: u32,
+    // memory traffic (0 in Mode::ReferenceCompatible)
+    pub bytes_fetched: u64,
+    pub bytes_written_back: u64,
+    // these two vectors make this whole thing so slow :(
+    // (they are for the chart example in main)
     pub hit_history: Vec<u32>,
     pub accesses_history: Vec<u32>,
+    // all zeros without a prefetcher (see Cache::set_prefetcher)
+    pub prefetch: PrefetchStats,
+    // empty without an Mmu (see Cache::set_mmu)
+    pub tlb: TlbStats,
+}
+
+// what simulate counts while a trace runs, for caches that get their accesses one at a time
+// instead of from a TraceSource (e.g. a lower level fed by the cache above it, see
+// Config::simulate):
+//
+// let mut simulation = Simulation::start(&cache);
+// simulation.access(&mut cache, access); // for every access
+// let results = simulation.finish(&cache);
+#[derive(Debug)]
+pub struct Simulation {
+    results: SimResults,
+    prefetch_stats_before: PrefetchStats,
+    writebacks_before: u32,
+    bytes_before: (u64, u64),
+    tlb_stats_before: Option<TlbStats>,
+}
+
+impl Simulation {
+    pub fn start(cache: &Cache) -> Self {
+        Simulation {
+            results: SimResults::default(),
+            prefetch_stats_before: cache.prefetch_stats,
+            writebacks_before: cache.writebacks,
+            bytes_before: (cache.bytes_fetched, cache.bytes_written_back),
+            tlb_stats_before: cache.mmu.as_ref().map(|mmu| mmu.stats().clone()),
+        }
+    }
+
+    pub fn access(&mut self, cache: &mut Cache, access: Access) {
+        // loads and stores get the same hits and misses according to the old simulator
+        // (stores just make the line dirty)
+        let outcome = cache.access(access.address, access.kind);
+        let results = &mut self.results;
+        results.record(outcome.hit);
+        if outcome.victim_hit {
+            results.victim_hits += 1;
+        }
+        if access.kind == AccessKind::Load {
+            results.loads += 1;
+            results.load_misses += !outcome.hit as u32;
+            results.load_victim_hits += outcome.victim_hit as u32;
+        }
+        if outcome.sector_miss {
+            results.sector_misses += 1;
+        }
+    }
+
+    // the results of everything since start (on the same cache)
+    pub fn finish(self, cache: &Cache) -> SimResults {
+        let mut results = self.results;
+        results.prefetch = cache.prefetch_stats.since(&self.prefetch_stats_before);
+        results.writebacks = cache.writebacks - self.writebacks_before;
+        results.bytes_fetched = cache.bytes_fetched - self.bytes_before.0;
+        results.bytes_written_back = cache.bytes_written_back - self.bytes_before.1;
+        if let (Some(mmu), Some(before)) = (&cache.mmu, self.tlb_stats_before) {
+            results.tlb = mmu.stats().since(&before);
+        }
+        results
+    }
+}
+
+impl SimResults {
+    // count one access
+    pub(crate) fn record(&mut self, hit: bool) {
+        self.accesses += 1;
+
+        // slow stuff for the chart example in main
+        self.hit_history.push(self.hits);
+        self.accesses_history.push(self.accesses);
+
+        if hit {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::user_input::tests::any_config;
+    use crate::user_input::{CacheType, ReplacementPolicy};
+    use proptest::prelude::*;
+
+    // a random trace, mostly from a small range of addresses so there are hits to be had
+    fn any_addresses() -> impl Strategy<Value = Vec<u32>> {
+        prop::collection::vec(prop_oneof![4 => 0u32..2048, 1 => any::<u32>()], 0..300)
+    }
+
+    // turn addresses into the contents of a trace file
+    fn to_trace(addresses: &[u32]) -> String {
+        addresses.iter().map(|address| format!("l 0x{:08X} 1\n", address)).collect()
+    }
 
     #[test]
     fn test_hex_to_binary_address() {
@@ -273,4 +819,249 @@ mod tests {
         let expected = "000100100011010001010110011110001001101010111100110111101111".to_string();
         assert_eq!(actual, expected);
     }
+
+    // writes down everything it gets told about
+    #[derive(Default)]
+    struct EventLog(Vec<String>);
+
+    impl CacheObserver for EventLog {
+        fn on_hit(&mut self, address: u32, kind: AccessKind) {
+            self.0.push(format!("hit {:x} {:?}", address, kind));
+        }
+        fn on_miss(&mut self, address: u32, kind: AccessKind) {
+            self.0.push(format!("miss {:x} {:?}", address, kind));
+        }
+        fn on_fill(&mut self, address: u32) {
+            self.0.push(format!("fill {:x}", address));
+        }
+        fn on_eviction(&mut self, victim_address: u32, dirty: bool) {
+            self.0.push(format!("evict {:x} {}", victim_address, dirty));
+        }
+        fn on_writeback(&mut self, address: u32, _dirty_sectors: u64, _sector_size: u32) {
+            self.0.push(format!("writeback {:x}", address));
+        }
+    }
+
+    #[test]
+    fn test_observer_sees_dirty_evictions() {
+        // one 16 byte line
+        let ui = UserInput::with_config(4, 4, CacheType::DirectMapped(0), ReplacementPolicy::LRU);
+        let mut log = EventLog::default();
+
+        let mut cache = Cache::new(&ui);
+        cache.add_observer(&mut log);
+        cache.simulate_trace("s 0x00000104 1\nl 0x0000010c 1\nl 0x00000200 1\nl 0x00000104 1\n");
+        drop(cache);
+
+        assert_eq!(
+            log.0,
+            vec![
+                "miss 100 Store",
+                "fill 100",
+                "hit 100 Load",
+                "miss 200 Load",
+                "evict 100 true",
+                "writeback 100",
+                "fill 200",
+                "miss 100 Load",
+                "evict 200 false",
+                "fill 100",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_access_outcome() {
+        // 4 lines of 16 bytes, direct mapped (so 2 set bits)
+        let ui = UserInput::with_config(6, 4, CacheType::DirectMapped(0), ReplacementPolicy::LRU);
+        let mut cache = Cache::new(&ui);
+
+        let outcome = cache.access(0x1234_5678, AccessKind::Store);
+        assert_eq!(outcome, AccessOutcome { hit: false, set: 0b11, tag: 0x1234_5678 >> 6, evicted: None, sector_miss: false, victim_hit: false });
+        assert!(cache.access(0x1234_567f, AccessKind::Load).hit);
+
+        // same set, different tag
+        let outcome = cache.access(0x0000_0070, AccessKind::Load);
+        assert_eq!(outcome, AccessOutcome { hit: false, set: 0b11, tag: 1, evicted: Some(Eviction { address: 0x1234_5670, dirty: true }), sector_miss: false, victim_hit: false });
+    }
+
+    // a policy that always kicks out way 0 (not a good one)
+    #[derive(Debug, Default)]
+    struct AlwaysWayZero;
+
+    impl replacement::ReplacementPolicy for AlwaysWayZero {
+        fn init(&mut self, _num_sets: u32, _ways: u32) {}
+        fn on_hit(&mut self, _set: u32, _way: u32) {}
+        fn on_fill(&mut self, _set: u32, _way: u32) {}
+        fn choose_victim(&mut self, _set: u32) -> u32 {
+            0
+        }
+    }
+
+    #[test]
+    fn test_custom_replacement_policy() {
+        // 2 lines of 4 bytes in one set
+        let ui = UserInput::with_config(3, 2, CacheType::FullyAssociative(1), ReplacementPolicy::LRU);
+        let trace = "l 0x00000010 1\nl 0x00000000 1\nl 0x00000010 1\nl 0x00000020 1\nl 0x00000010 1\n";
+
+        // LRU kicks out 0x00 to make room for 0x20, so 0x10 hits twice
+        assert_eq!(Cache::new(&ui).simulate_trace(trace).hits, 2);
+
+        // 0x10 is in way 0, so it gets kicked out instead
+        let mut cache = Cache::new(&ui);
+        cache.set_replacement_policy(Box::new(AlwaysWayZero));
+        assert_eq!(cache.simulate_trace(trace).hits, 1);
+
+        // it doesn't know about skewed indexing, so the default asks every candidate's set and
+        // goes with the candidate in way 0 (or the first candidate if there isn't one)
+        let mut policy = AlwaysWayZero;
+        assert_eq!(replacement::ReplacementPolicy::choose_skewed_victim(&mut policy, &[(3, 1), (5, 0)]), 0);
+        assert_eq!(replacement::ReplacementPolicy::choose_skewed_victim(&mut policy, &[(3, 1), (5, 2)]), 1);
+    }
+
+    #[test]
+    fn test_victim_cache_swaps() {
+        // 4 lines of 16 bytes, direct mapped, so 0x000 and 0x040 fight over set 0
+        let ui = UserInput::with_config(6, 4, CacheType::DirectMapped(0), ReplacementPolicy::LRU);
+        let trace = "s 0x00000000 1\nl 0x00000040 1\nl 0x00000000 1\nl 0x00000040 1\nl 0x00000080 1\nl 0x000000c0 1\n";
+        assert_eq!(Cache::new(&ui).simulate_trace(trace).victim_hits, 0);
+
+        let mut log = EventLog::default();
+        let mut cache = Cache::new(&ui);
+        cache.set_victim_cache(1);
+        cache.add_observer(&mut log);
+        let results = cache.simulate_trace(trace);
+        drop(cache);
+
+        assert_eq!((results.hits, results.misses, results.victim_hits), (0, 6, 2));
+        // the dirty line only gets written back once it leaves the victim cache too
+        let writebacks: Vec<_> = log.0.iter().filter(|event| event.starts_with("writeback")).collect();
+        assert_eq!(writebacks, vec!["writeback 0"]);
+    }
+
+    #[test]
+    fn test_setters_empty_the_victim_cache() {
+        let ui = UserInput::with_config(6, 4, CacheType::DirectMapped(0), ReplacementPolicy::LRU);
+        let mut cache = Cache::new(&ui);
+        cache.set_victim_cache(1);
+        // 0x000 ends up in the victim cache, and would be a victim hit
+        cache.simulate_trace("l 0x00000000 1\nl 0x00000040 1\n");
+
+        cache.set_sectors(2);
+        assert_eq!(cache.simulate_trace("l 0x00000000 1\n").victim_hits, 0);
+    }
+
+    #[test]
+    fn test_sectors() {
+        // one 16 byte line in 4 byte sectors
+        let ui = UserInput::with_config(4, 4, CacheType::DirectMapped(0), ReplacementPolicy::LRU);
+        let mut cache = Cache::new(&ui);
+        cache.set_sectors(4);
+
+        // line miss, sector miss, hit, sector miss (dirty), then 0x200 kicks the line out
+        let results = cache.simulate_trace("l 0x00000100 4\nl 0x00000104 4\nl 0x00000100 4\ns 0x0000010c 4\nl 0x00000200 4\n");
+        assert_eq!((results.hits, results.misses, results.sector_misses), (1, 4, 2));
+        // 4 sectors loaded, and only the dirty one written back
+        assert_eq!((results.bytes_fetched, results.bytes_written_back), (16, 4));
+    }
+
+    #[test]
+    fn test_modulo_sets() {
+        // 3 sets of 2 ways with 16 byte lines, so lines 0, 3, 6 and 9 all go in set 0
+        let ui = UserInput::from_sizes(96, 16, 2, "l").unwrap();
+        let mut cache = Cache::new(&ui);
+        let outcome = cache.access(0x90, AccessKind::Load);
+        assert_eq!((outcome.set, outcome.tag), (0, 3));
+
+        // 0x30 and 0x60 fill set 0, then 0x90 comes back and kicks out 0x30 (LRU)
+        let results = cache.simulate_trace("l 0x00000030 1
+l 0x00000060 1
+l 0x00000010 1
+l 0x00000090 1
+l 0x00000030 1
+");
+        assert_eq!((results.hits, results.misses), (0, 5));
+        assert_eq!(cache.cache.len(), 2);
+    }
+
+    #[test]
+    fn test_prefetch_stats() {
+        // 4 lines of 16 bytes, direct mapped
+        let ui = UserInput::with_config(6, 4, CacheType::DirectMapped(0), ReplacementPolicy::LRU);
+        let mut cache = Cache::new(&ui);
+        cache.set_prefetcher(Box::new(prefetch::NextLinePrefetcher::new(1)));
+        cache.set_prefetch_latency(2);
+
+        // 0x10, 0x20 and 0x30 get prefetched right before they're used, then prefetching 0x40
+        // kicks out 0x00 (and 0x40 gets kicked out again without being used)
+        let results = cache.simulate_trace("l 0x00000000 1\nl 0x00000010 1\nl 0x00000020 1\nl 0x00000030 1\nl 0x00000000 1\n");
+        assert_eq!(results.hits, 3);
+        assert_eq!(results.prefetch, PrefetchStats { issued: 4, useful: 3, late: 3, unused: 1, pollution: 1 });
+    }
+
+    #[test]
+    fn test_set_dueling_only_counts_demand_misses() {
+        // 16 sets of 2 ways with 64 byte lines in 4 sectors, and a prefetcher filling lines the
+        // trace never asks for
+        let ui = UserInput::with_config(11, 6, CacheType::SetAssociative(1), ReplacementPolicy::DRRIP);
+        let mut drrip = replacement::RripPolicy::drrip(2).with_phase_length(1_000_000);
+        let mut cache = Cache::new(&ui);
+        cache.set_replacement_policy(Box::new(&mut drrip));
+        cache.set_sectors(4);
+        cache.set_prefetcher(Box::new(prefetch::NextLinePrefetcher::new(1)));
+
+        let trace: String = (0..400u32).map(|i| format!("l 0x{:08x} 4\n", (i / 2 * 5 % 40) * 0x100 + (i % 2) * 16)).collect();
+        let results = cache.simulate_trace(&trace);
+        drop(cache);
+
+        assert!(results.prefetch.issued > 0 && results.sector_misses > 0);
+        let phase = &drrip.phases()[0];
+        let misses = phase.leader_misses().iter().chain(&phase.follower_fills()).sum::<u64>();
+        assert_eq!((phase.accesses(), misses), (results.accesses as u64, results.misses as u64));
+    }
+
+    proptest! {
+        #[test]
+        fn prop_sets_never_overflow((cache_size_exp, line_size_exp, cache_type, policy) in any_config(), addresses in any_addresses()) {
+            let ui = UserInput::with_config(cache_size_exp, line_size_exp, cache_type, policy);
+            let mut cache = Cache::new(&ui);
+
+            for address in addresses {
+                cache.write(&format!("{:032b}", address), AccessKind::Load);
+
+                prop_assert!(cache.cache.len() as u32 <= ui.num_sets());
+                for lines in cache.cache.values() {
+                    prop_assert!(lines.len() as u32 <= ui.lines_per_set());
+                }
+            }
+        }
+
+        #[test]
+        fn prop_hits_plus_misses_is_accesses((cache_size_exp, line_size_exp, cache_type, policy) in any_config(), addresses in any_addresses()) {
+            let ui = UserInput::with_config(cache_size_exp, line_size_exp, cache_type, policy);
+            let trace = to_trace(&addresses);
+
+            let results = Cache::new(&ui).simulate_trace(&trace);
+            prop_assert_eq!(results.accesses as usize, addresses.len());
+            prop_assert_eq!(results.hits + results.misses, results.accesses);
+
+            let results = Cache::with_mode(&ui, Mode::ReferenceCompatible).simulate_trace(&trace);
+            prop_assert_eq!(results.hits + results.misses, results.accesses);
+        }
+
+        // a bigger fully associative LRU cache holds everything a smaller one does
+        // (the inclusion property), so it can never get fewer hits
+        #[test]
+        fn prop_fully_associative_lru_hits_grow_with_size(line_size_exp in 0u32..=4, lines_exp in 0u32..=7, addresses in any_addresses()) {
+            let trace = to_trace(&addresses);
+            let cache_size_exp = line_size_exp + lines_exp;
+
+            let small = UserInput::with_config(cache_size_exp, line_size_exp, CacheType::FullyAssociative(lines_exp), ReplacementPolicy::LRU);
+            let big = UserInput::with_config(cache_size_exp + 1, line_size_exp, CacheType::FullyAssociative(lines_exp + 1), ReplacementPolicy::LRU);
+
+            let small_hits = Cache::new(&small).simulate_trace(&trace).hits;
+            let big_hits = Cache::new(&big).simulate_trace(&trace).hits;
+            prop_assert!(small_hits <= big_hits, "{} byte cache got {} hits, {} byte cache got {}", 1u32 << cache_size_exp, small_hits, 1u32 << (cache_size_exp + 1), big_hits);
+        }
+    }
 }
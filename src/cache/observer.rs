@@ -0,0 +1,227 @@
+use std::io::{self, Write};
+
+use super::{Access, AccessKind};
+
+// get told about everything that happens inside a Cache
+//
+// register one with Cache::add_observer. Every method does nothing by default, so only
+// implement the ones you care about. Addresses are line addresses (offset bits are 0).
+//
+// observers aren't called in Mode::ReferenceCompatible (the cpp simulator doesn't have them)
+//
+// when an access misses you get (in this order):
+// on_miss, on_fetch (unless the victim cache had it), on_eviction (if the set was full),
+// on_writeback (if the victim was dirty), on_fill
+pub trait CacheObserver {
+    // the line was already in the cache
+    fn on_hit(&mut self, _address: u32, _kind: AccessKind) {}
+
+    // the line was not in the cache
+    fn on_miss(&mut self, _address: u32, _kind: AccessKind) {}
+
+    // size bytes at address got read from the next level down for a miss: the sector that
+    // missed (the whole line without sectors, see Cache::set_sectors), so this address isn't
+    // always a line address
+    //
+    // misses the victim cache catches don't read anything. Prefetches read whole lines (and
+    // come before that line's on_fill)
+    fn on_fetch(&mut self, _address: u32, _size: u32) {}
+
+    // a line was loaded into the cache
+    fn on_fill(&mut self, _address: u32) {}
+
+    // a line got kicked out to make room
+    fn on_eviction(&mut self, _victim_address: u32, _dirty: bool) {}
+
+    // a dirty line got written back to memory
+    //
+    // only its dirty sectors go: bit i of dirty_sectors is the sector at address + i *
+    // sector_size (without sectors that's bit 0 and the whole line, see writeback_stores). With
+    // a victim cache this is when a dirty line leaves the victim cache, not the cache
+    fn on_writeback(&mut self, _address: u32, _dirty_sectors: u64, _sector_size: u32) {}
+}
+
+// a store for every dirty sector in a writeback (see CacheObserver::on_writeback)
+pub fn writeback_stores(address: u32, dirty_sectors: u64, sector_size: u32) -> impl Iterator<Item = Access> {
+    (0..u64::BITS)
+        .filter(move |sector| dirty_sectors >> sector & 1 == 1)
+        .map(move |sector| Access { kind: AccessKind::Store, address: address + sector * sector_size, size: sector_size })
+}
+
+// writes down what a cache asks the next level down for: a load for every fetch (see
+// CacheObserver::on_fetch, prefetches too) and a store for every dirty sector of every writeback,
+// as a trace (see config, which feeds the same accesses to the next cache)
+//
+// misses that the victim cache catches aren't in it
+#[derive(Debug, Default)]
+pub struct MissStream {
+    pub accesses: Vec<Access>,
+}
+
+impl MissStream {
+    pub fn new() -> Self {
+        MissStream::default()
+    }
+}
+
+impl CacheObserver for MissStream {
+    // a store that misses still has to read the line first (the dirty data goes down later, as
+    // a writeback)
+    fn on_fetch(&mut self, address: u32, size: u32) {
+        self.accesses.push(Access { kind: AccessKind::Load, address, size });
+    }
+
+    fn on_writeback(&mut self, address: u32, dirty_sectors: u64, sector_size: u32) {
+        self.accesses.extend(writeback_stores(address, dirty_sectors, sector_size));
+    }
+}
+
+// the same accesses as MissStream, but written out as a trace file as they happen (so the
+// misses of a huge trace don't have to fit in memory), e.g. to feed to a lower level cache
+// simulated somewhere else:
+//
+// let mut misses = MissTraceWriter::new(BufWriter::new(File::create("misses.trace")?));
+// cache.add_observer(&mut misses);
+//
+// observers can't return errors, so writing stops at the first one and finish returns it
+#[derive(Debug)]
+pub struct MissTraceWriter<W: Write> {
+    out: W,
+    // how many accesses have been written
+    pub accesses: u64,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> MissTraceWriter<W> {
+    pub fn new(out: W) -> Self {
+        MissTraceWriter { out, accesses: 0, error: None }
+    }
+
+    fn write(&mut self, access: Access) {
+        if self.error.is_some() {
+            return;
+        }
+        match writeln!(self.out, "{}", access) {
+            Ok(()) => self.accesses += 1,
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    // flush everything out and hand back the writer (or the first thing that went wrong)
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+impl<W: Write> CacheObserver for MissTraceWriter<W> {
+    fn on_fetch(&mut self, address: u32, size: u32) {
+        self.write(Access { kind: AccessKind::Load, address, size });
+    }
+
+    fn on_writeback(&mut self, address: u32, dirty_sectors: u64, sector_size: u32) {
+        for store in writeback_stores(address, dirty_sectors, sector_size) {
+            self.write(store);
+        }
+    }
+}
+
+// the observers registered on a Cache
+//
+// this only exists so Cache can still derive Debug (trait objects can't)
+#[derive(Default)]
+pub(crate) struct Observers<'a>(pub(crate) Vec<&'a mut dyn CacheObserver>);
+
+impl std::fmt::Debug for Observers<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} observer(s)", self.0.len())
+    }
+}
+
+impl Observers<'_> {
+    // call f on every observer
+    pub(crate) fn notify(&mut self, f: impl Fn(&mut dyn CacheObserver)) {
+        for observer in self.0.iter_mut() {
+            f(&mut **observer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::trace::ReaderSource;
+    use crate::cache::{Cache, TraceSource};
+    use crate::user_input::{CacheType, ReplacementPolicy, UserInput};
+
+    #[test]
+    fn test_miss_trace_is_a_trace() {
+        // 2 lines of 16 bytes, direct mapped: 0x00 and 0x20 fight over set 0
+        let ui = UserInput::with_config(5, 4, CacheType::DirectMapped(0), ReplacementPolicy::LRU);
+        let trace = "s 0x00000004 4\nl 0x00000024 4\nl 0x00000010 4\nl 0x00000018 4\n";
+
+        let mut stream = MissStream::new();
+        let mut writer = MissTraceWriter::new(Vec::new());
+        let mut cache = Cache::new(&ui);
+        cache.add_observer(&mut stream);
+        cache.add_observer(&mut writer);
+        cache.simulate(ReaderSource::new(trace.as_bytes(), "test"));
+        drop(cache);
+
+        let written = String::from_utf8(writer.finish().unwrap()).unwrap();
+        assert_eq!(written, "l 0x00000000 16\nl 0x00000020 16\ns 0x00000000 16\nl 0x00000010 16\n");
+
+        // and it reads back in as the same accesses MissStream kept
+        let mut source = ReaderSource::new(written.as_bytes(), "misses");
+        let read_back: Vec<_> = std::iter::from_fn(|| source.next_access()).collect();
+        assert_eq!(read_back, stream.accesses);
+    }
+
+    #[test]
+    fn test_misses_behind_a_victim_cache_and_in_sectors() {
+        // the same cache with a 1 line victim cache: the 3rd and 4th loads find their lines in
+        // it, so they don't go to memory
+        let ui = UserInput::with_config(5, 4, CacheType::DirectMapped(0), ReplacementPolicy::LRU);
+        let trace = "l 0x00000000 4\nl 0x00000020 4\nl 0x00000000 4\nl 0x00000020 4\n";
+        let mut stream = MissStream::new();
+        let mut cache = Cache::new(&ui);
+        cache.set_victim_cache(1);
+        cache.add_observer(&mut stream);
+        let results = cache.simulate_trace(trace);
+        drop(cache);
+
+        assert_eq!((results.misses, results.victim_hits), (4, 2));
+        let addresses: Vec<_> = stream.accesses.iter().map(|access| access.address).collect();
+        assert_eq!(addresses, vec![0x00, 0x20]);
+
+        // with 4 byte sectors only the sector that missed gets loaded, and when 0x20 kicks the
+        // line out only its 2 dirty sectors get written back
+        let mut stream = MissStream::new();
+        let mut cache = Cache::new(&ui);
+        cache.set_sectors(4);
+        cache.add_observer(&mut stream);
+        cache.simulate_trace("l 0x00000004 4\ns 0x0000000c 4\ns 0x00000000 4\nl 0x00000020 4\n");
+        drop(cache);
+
+        let access = |kind, address| Access { kind, address, size: 4 };
+        let (load, store) = (AccessKind::Load, AccessKind::Store);
+        assert_eq!(
+            stream.accesses,
+            vec![access(load, 0x04), access(load, 0x0c), access(load, 0x00), access(load, 0x20), access(store, 0x00), access(store, 0x0c)]
+        );
+
+        // prefetches go to memory too, a whole line at a time
+        let mut stream = MissStream::new();
+        let mut cache = Cache::new(&ui);
+        cache.set_prefetcher(Box::new(crate::cache::prefetch::NextLinePrefetcher::new(1)));
+        cache.add_observer(&mut stream);
+        cache.simulate_trace("l 0x00000004 4\n");
+        drop(cache);
+
+        let fetches: Vec<_> = stream.accesses.iter().map(|access| (access.address, access.size)).collect();
+        assert_eq!(fetches, vec![(0x00, 16), (0x10, 16)]);
+    }
+}
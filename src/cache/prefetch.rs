@@ -0,0 +1,291 @@
+use super::AccessKind;
+
+// a demand access, as a prefetcher sees it (see Prefetcher::on_access)
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct DemandAccess {
+    pub line_address: u32, // offset bits are 0
+    pub kind: AccessKind,
+    pub hit: bool,
+    // the line was brought in by a prefetch and this is the first time it got used
+    // (a prefetcher that only looks at misses would lose track of a stream it's covering
+    // without this)
+    pub first_use_of_prefetch: bool,
+}
+
+// guesses which lines are going to be needed soon so the cache can load them early
+//
+// attach one with Cache::set_prefetcher. After every demand access the cache asks the
+// prefetcher which lines to bring in, and loads the ones that aren't there already (they go
+// through the replacement policy like any other fill).
+//
+// NextLinePrefetcher, StridePrefetcher and StreamPrefetcher are here, but you can write your own
+pub trait Prefetcher: std::fmt::Debug {
+    // called once before the cache uses the prefetcher
+    fn init(&mut self, line_size: u32);
+
+    // a demand access happened, which line addresses should be prefetched?
+    fn on_access(&mut self, access: &DemandAccess) -> Vec<u32>;
+}
+
+// lend a prefetcher to a cache instead of giving it away, so you can look at it afterwards
+impl<T: Prefetcher + ?Sized> Prefetcher for &mut T {
+    fn init(&mut self, line_size: u32) {
+        (**self).init(line_size)
+    }
+
+    fn on_access(&mut self, access: &DemandAccess) -> Vec<u32> {
+        (**self).on_access(access)
+    }
+}
+
+// the prefetcher called next-line, stride or stream (with the settings the command line uses,
+// build one yourself for anything else)
+pub fn from_name(name: &str) -> Result<Box<dyn Prefetcher>, String> {
+    match name.to_lowercase().trim() {
+        "next-line" => Ok(Box::new(NextLinePrefetcher::new(1))),
+        "stride" => Ok(Box::new(StridePrefetcher::new(2, 2))),
+        "stream" => Ok(Box::new(StreamPrefetcher::new(4, 4))),
+        other => Err(format!("{} is not next-line, stride or stream", other)),
+    }
+}
+
+// the line address `lines` lines away from line_address (None if it's off either end of memory)
+fn lines_away(line_address: u32, lines: i64, line_size: u32) -> Option<u32> {
+    let address = line_address as i64 + lines * line_size as i64;
+    u32::try_from(address).ok()
+}
+
+// on a miss (or the first use of a prefetched line) grab the next `degree` lines
+//
+// the "tagged" part (prefetching on first use too) keeps it one step ahead of a sequential scan
+// instead of missing every other line
+#[derive(Debug)]
+pub struct NextLinePrefetcher {
+    degree: u32,
+    line_size: u32,
+}
+
+impl NextLinePrefetcher {
+    pub fn new(degree: u32) -> Self {
+        assert!(degree > 0, "prefetching 0 lines doesn't do anything");
+        NextLinePrefetcher { degree, line_size: 0 }
+    }
+}
+
+impl Prefetcher for NextLinePrefetcher {
+    fn init(&mut self, line_size: u32) {
+        self.line_size = line_size;
+    }
+
+    fn on_access(&mut self, access: &DemandAccess) -> Vec<u32> {
+        if access.hit && !access.first_use_of_prefetch {
+            return Vec::new();
+        }
+
+        (1..=self.degree as i64)
+            .filter_map(|lines| lines_away(access.line_address, lines, self.line_size))
+            .collect()
+    }
+}
+
+// notices when the program keeps jumping the same distance between lines and prefetches the
+// next `degree` lines along that stride
+//
+// real stride prefetchers keep a table indexed by the PC of the load, but traces don't have
+// PCs, so this only tracks one stride for the whole address stream. A stride has to show up
+// `threshold` times in a row before anything gets prefetched.
+#[derive(Debug)]
+pub struct StridePrefetcher {
+    degree: u32,
+    threshold: u32,
+    line_size: u32,
+    last_line: Option<u32>,
+    stride: i64, // in lines
+    confidence: u32,
+}
+
+impl StridePrefetcher {
+    pub fn new(degree: u32, threshold: u32) -> Self {
+        assert!(degree > 0, "prefetching 0 lines doesn't do anything");
+        StridePrefetcher { degree, threshold, line_size: 0, last_line: None, stride: 0, confidence: 0 }
+    }
+}
+
+impl Prefetcher for StridePrefetcher {
+    fn init(&mut self, line_size: u32) {
+        self.line_size = line_size;
+        self.last_line = None;
+        self.stride = 0;
+        self.confidence = 0;
+    }
+
+    fn on_access(&mut self, access: &DemandAccess) -> Vec<u32> {
+        let line = access.line_address / self.line_size;
+        let last_line = self.last_line.replace(line);
+
+        // accesses inside the same line don't say anything about the stride
+        let stride = match last_line {
+            Some(last_line) if last_line != line => line as i64 - last_line as i64,
+            _ => return Vec::new(),
+        };
+
+        if stride == self.stride {
+            self.confidence = self.confidence.saturating_add(1);
+        } else {
+            self.stride = stride;
+            self.confidence = 1;
+        }
+
+        if self.confidence < self.threshold {
+            return Vec::new();
+        }
+
+        (1..=self.degree as i64)
+            .filter_map(|steps| lines_away(access.line_address, steps * self.stride, self.line_size))
+            .collect()
+    }
+}
+
+// one sequential stream the StreamPrefetcher is following
+#[derive(Debug, Clone, Copy)]
+struct Stream {
+    next: i64, // the next line the program should want (in lines, not bytes)
+    tail: i64, // the last line that got prefetched
+    last_used: u64,
+}
+
+// stream buffers (Jouppi, ISCA 1990)
+//
+// a miss that isn't part of any stream starts a new one (kicking out the least recently used
+// stream if all of them are taken) and prefetches the next `depth` lines. Whenever the program
+// gets to a line a stream covers, the stream moves along and stays `depth` lines ahead.
+//
+// the prefetched lines go straight into the cache instead of a separate buffer, so the stats
+// mean the same thing for every prefetcher. Streams only go up (like the original ones).
+#[derive(Debug)]
+pub struct StreamPrefetcher {
+    num_streams: usize,
+    depth: u32,
+    line_size: u32,
+    streams: Vec<Stream>,
+    clock: u64,
+}
+
+impl StreamPrefetcher {
+    pub fn new(num_streams: usize, depth: u32) -> Self {
+        assert!(num_streams > 0 && depth > 0, "need at least one stream that is at least one line deep");
+        StreamPrefetcher { num_streams, depth, line_size: 0, streams: Vec::new(), clock: 0 }
+    }
+
+    // line addresses for lines first..=last
+    fn lines(&self, first: i64, last: i64) -> Vec<u32> {
+        (first..=last)
+            .filter_map(|line| lines_away(0, line, self.line_size))
+            .collect()
+    }
+}
+
+impl Prefetcher for StreamPrefetcher {
+    fn init(&mut self, line_size: u32) {
+        self.line_size = line_size;
+        self.streams.clear();
+        self.clock = 0;
+    }
+
+    fn on_access(&mut self, access: &DemandAccess) -> Vec<u32> {
+        self.clock += 1;
+        let line = (access.line_address / self.line_size) as i64;
+        let depth = self.depth as i64;
+
+        if let Some(stream) = self.streams.iter_mut().find(|stream| (stream.next..=stream.tail).contains(&line)) {
+            let old_tail = stream.tail;
+            stream.next = line + 1;
+            stream.tail = line + depth;
+            stream.last_used = self.clock;
+            let tail = stream.tail;
+            return self.lines(old_tail + 1, tail);
+        }
+
+        if access.hit {
+            return Vec::new();
+        }
+
+        let stream = Stream { next: line + 1, tail: line + depth, last_used: self.clock };
+        if self.streams.len() < self.num_streams {
+            self.streams.push(stream);
+        } else {
+            let oldest = self.streams.iter_mut().min_by_key(|stream| stream.last_used).unwrap();
+            *oldest = stream;
+        }
+
+        self.lines(line + 1, line + depth)
+    }
+}
+
+// how well the prefetcher did (see SimResults::prefetch)
+//
+// every prefetched line ends up as exactly one of useful or unused, unless it's still sitting
+// in the cache when the simulation ends
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct PrefetchStats {
+    // lines the prefetcher asked for that weren't in the cache already (so they got loaded)
+    pub issued: u32,
+    // prefetched lines that got used before they were kicked out
+    pub useful: u32,
+    // useful prefetches that got used before they would have arrived (see
+    // Cache::set_prefetch_latency), a real cache would still have waited for them a bit
+    pub late: u32,
+    // prefetched lines that got kicked out without ever being used
+    pub unused: u32,
+    // demand misses on lines that a prefetch kicked out (the prefetcher made things worse)
+    pub pollution: u32,
+}
+
+impl PrefetchStats {
+    // what happened between `before` and now
+    pub(crate) fn since(&self, before: &PrefetchStats) -> PrefetchStats {
+        PrefetchStats {
+            issued: self.issued - before.issued,
+            useful: self.useful - before.useful,
+            late: self.late - before.late,
+            unused: self.unused - before.unused,
+            pollution: self.pollution - before.pollution,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn demand(line_address: u32, hit: bool) -> DemandAccess {
+        DemandAccess { line_address, kind: AccessKind::Load, hit, first_use_of_prefetch: false }
+    }
+
+    #[test]
+    fn test_stride_needs_confidence() {
+        let mut stride = StridePrefetcher::new(2, 2);
+        stride.init(16);
+
+        assert!(stride.on_access(&demand(0x100, false)).is_empty());
+        // one jump of 3 lines isn't a pattern yet
+        assert!(stride.on_access(&demand(0x130, false)).is_empty());
+        assert_eq!(stride.on_access(&demand(0x160, false)), vec![0x190, 0x1c0]);
+
+        // a different jump starts over
+        assert!(stride.on_access(&demand(0x100, false)).is_empty());
+    }
+
+    #[test]
+    fn test_stream_stays_ahead() {
+        let mut stream = StreamPrefetcher::new(2, 3);
+        stream.init(16);
+
+        assert_eq!(stream.on_access(&demand(0x100, false)), vec![0x110, 0x120, 0x130]);
+        // 0x120 is in the stream, so it moves up to 0x150
+        assert_eq!(stream.on_access(&demand(0x120, true)), vec![0x140, 0x150]);
+        // hits outside any stream don't start one
+        assert!(stream.on_access(&demand(0x800, true)).is_empty());
+        assert_eq!(stream.on_access(&demand(0x800, false)), vec![0x810, 0x820, 0x830]);
+    }
+}
@@ -0,0 +1,171 @@
+use crate::user_input::UserInput;
+
+use super::SimResults;
+
+// a line-for-line port of checkCache from default_cache_sim.cpp
+//
+// the Cache in mod.rs simulates what a cache should do, this simulates what the cpp simulator
+// actually does. They agree most of the time, but this one keeps the cpp's quirks:
+//  - tags are ints, so a 32 bit tag can overflow and a tag of -1 "hits" on an empty line
+//  - direct mapped caches (and anything with 1 line per set) use their own little path
+//  - FIFO is just LRU that never updates the counter on a hit
+//  - addresses with less than 8 hex digits are read from the left (0x000AA40 is 0x000AA40_)
+//
+// and the main loop's quirks are in simulate_trace
+#[derive(Debug)]
+pub struct ReferenceCache {
+    // each line is [tag, access counter], both -1 when the line is empty
+    lines: Vec<[i32; 2]>,
+    counter: i32,
+    tag_size: u32,
+    num_sets_exp: u32,
+    set_size_exp: u32,
+    // LRU or FIFO, the only policies the cpp has (Cache::with_mode won't make one with anything
+    // else)
+    lru: bool,
+}
+
+impl ReferenceCache {
+    pub fn new(user_input: &UserInput) -> Self {
+        ReferenceCache {
+            lines: vec![[-1, -1]; user_input.num_lines() as usize],
+            counter: 0,
+            tag_size: user_input.tag_size(),
+            num_sets_exp: user_input.num_sets_exp(),
+            set_size_exp: user_input.set_size_exp(),
+            lru: user_input.replacement_policy() == crate::user_input::ReplacementPolicy::LRU,
+        }
+    }
+
+    // the main loop from the cpp, with the contents of a trace file
+    //
+    // the cpp loops until eof, so a trailing newline counts as one more access.
+    // That access reads the last address again (its buffer still has the old address in it)
+    pub fn simulate_trace(&mut self, file: &str) -> SimResults {
+        let mut results = SimResults::default();
+        let mut last_hex_addy = None;
+
+        for line in file.lines() {
+            let line = line.trim().to_lowercase();
+
+            // get ls and address from the line
+            let mut line_iter = line.split(' ').take(2);
+            let ls = line_iter.next().unwrap();
+            let hex_addy = line_iter
+                .next()
+                .unwrap()
+                .strip_prefix("0x")
+                .expect("hex addresses should start with 0x");
+            assert!(hex_addy.len() <= 8, "addresses are 32 bits, got 0x{}", hex_addy);
+            assert!(ls == "l" || ls == "s", "we should only get l or s. Got {}", ls);
+
+            let hit = self.access(hex_addy);
+            results.record(hit);
+            last_hex_addy = Some(hex_addy.to_string());
+        }
+
+        if let Some(hex_addy) = last_hex_addy {
+            if file.ends_with('\n') {
+                let hit = self.access(&hex_addy);
+                results.record(hit);
+            }
+        }
+
+        results
+    }
+
+    // access some hex address (without the 0x)
+    //
+    // returns true on a hit
+    pub fn access(&mut self, hex_addy: &str) -> bool {
+        // the cpp reads the digits straight out of the string, so missing digits at the end
+        // come out as zeros
+        let binary_addy = format!("{:0<32}", super::hex_str_to_binary_str(hex_addy));
+
+        // getTag adds the bits up in an int, so a 32 bit tag wraps around
+        let tag = u32::from_str_radix(&binary_addy[..self.tag_size as usize], 2).unwrap_or(0) as i32;
+
+        // getSet is never called when there is only one set
+        let set = if self.num_sets_exp == 0 {
+            0
+        } else {
+            let set_bits = &binary_addy[self.tag_size as usize..(self.tag_size + self.num_sets_exp) as usize];
+            u32::from_str_radix(set_bits, 2).unwrap() as usize
+        };
+
+        let hit = self.check_cache(set, tag);
+        self.counter += 1;
+        hit
+    }
+
+    // checkCache from the cpp (comments and all)
+    fn check_cache(&mut self, set: usize, tag: i32) -> bool {
+        let counter = self.counter;
+
+        if self.set_size_exp == 0 {
+            //direct mapped
+            let line = &mut self.lines[set];
+            if line[0] == tag {
+                line[1] = counter;
+                return true;
+            } else {
+                line[0] = tag;
+                line[1] = counter;
+                return false;
+            }
+        }
+
+        let set_size = 2usize.pow(self.set_size_exp);
+        let j = set * set_size;
+        let mut empty_spot = None;
+        let mut smallest_counter = -1;
+        let mut line_to_replace = None;
+        for i in j..j + set_size {
+            if self.lines[i][0] == tag {
+                if self.lru {
+                    self.lines[i][1] = counter;
+                }
+                return true;
+            } else if self.lines[i][0] == -1 {
+                empty_spot = Some(i);
+            } else if smallest_counter == -1 || self.lines[i][1] < smallest_counter {
+                smallest_counter = self.lines[i][1];
+                line_to_replace = Some(i);
+            }
+        }
+
+        //empty spot?
+        //there was an empty spot, fill it, otherwise update entry with lowest counter
+        let line = empty_spot.or(line_to_replace).expect("a full set always has a line to replace");
+        self.lines[line] = [tag, counter];
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::user_input::{CacheType, ReplacementPolicy};
+
+    #[test]
+    fn test_all_ones_tag_hits_an_empty_line() {
+        // 1 byte lines and 4 lines in one set means the tag is the whole address
+        let ui = UserInput::with_config(2, 0, CacheType::FullyAssociative(2), ReplacementPolicy::LRU);
+        let mut reference = ReferenceCache::new(&ui);
+
+        // 0xffffffff is -1 as an int, which is what the cpp uses for "empty"
+        assert!(reference.access("ffffffff"));
+        assert!(!reference.access("fffffffe"));
+    }
+
+    #[test]
+    fn test_short_addresses_are_read_from_the_left() {
+        // 16 byte lines, so the last hex digit is the offset
+        let ui = UserInput::with_config(6, 4, CacheType::DirectMapped(0), ReplacementPolicy::LRU);
+        let mut reference = ReferenceCache::new(&ui);
+
+        assert!(!reference.access("000aa40"));
+        // 0x000aa40 is 0x000aa40_ to the cpp, so this is the same line
+        assert!(reference.access("000aa40f"));
+    }
+}
@@ -0,0 +1,146 @@
+// set dueling (Qureshi et al., ISCA 2007), shared by DRRIP and DIP
+//
+// two policies (the contenders, e.g. SRRIP and BRRIP) fight it out: a few leader sets always
+// use the first one, a few always use the second one, and every other set (the followers) uses
+// whichever leaders are missing less. PSEL keeps score: a miss in a first leader counts it up,
+// a miss in a second leader counts it down, and followers use the second contender when it's
+// over the halfway mark.
+//
+// caches with less than 4 sets don't have room for leaders and just use the first contender
+#[derive(Debug)]
+pub(crate) struct SetDueling<F> {
+    contenders: [F; 2],
+    leaders_per_contender: u32,
+    // sets are split into groups of this many, the first set of every group leads for the first
+    // contender and the second set for the second contender (0 means no leaders)
+    group_size: u32,
+    psel: u32,
+    psel_max: u32,
+    pub(crate) phase_length: u64,
+    phases: Vec<DuelPhase<F>>,
+}
+
+impl<F: Copy + PartialEq> SetDueling<F> {
+    pub(crate) fn new(first: F, second: F) -> Self {
+        SetDueling {
+            contenders: [first, second],
+            leaders_per_contender: 32,
+            group_size: 0,
+            psel: 0,
+            // 10 bit PSEL
+            psel_max: 1023,
+            phase_length: 10_000,
+            phases: Vec::new(),
+        }
+    }
+
+    pub(crate) fn init(&mut self, num_sets: u32) {
+        let leaders = self.leaders_per_contender.min(num_sets / 4);
+        self.group_size = num_sets.checked_div(leaders).unwrap_or(0);
+        // start out (just barely) on the first contender's side
+        self.psel = self.psel_max / 2;
+        self.phases.clear();
+    }
+
+    // which contender (0 or 1) does this set lead for? (None for followers)
+    fn leader(&self, set: u32) -> Option<usize> {
+        if self.group_size == 0 {
+            return None;
+        }
+
+        match set % self.group_size {
+            0 => Some(0),
+            1 => Some(1),
+            _ => None,
+        }
+    }
+
+    fn follower_choice(&self) -> usize {
+        if self.psel > self.psel_max / 2 {
+            1
+        } else {
+            0
+        }
+    }
+
+    // the contender this set should use right now
+    pub(crate) fn contender_for(&self, set: u32) -> F {
+        self.contenders[self.leader(set).unwrap_or_else(|| self.follower_choice())]
+    }
+
+    // count an access to a set (and who to blame if it missed)
+    pub(crate) fn record(&mut self, set: u32, miss: bool) {
+        if self.phases.last().is_none_or(|phase| phase.accesses >= self.phase_length) {
+            self.phases.push(DuelPhase {
+                contenders: self.contenders,
+                accesses: 0,
+                leader_misses: [0; 2],
+                follower_fills: [0; 2],
+            });
+        }
+
+        let leader = self.leader(set);
+        let follower_choice = self.follower_choice();
+        let phase = self.phases.last_mut().unwrap();
+        phase.accesses += 1;
+
+        if !miss {
+            return;
+        }
+
+        match leader {
+            Some(contender) => {
+                phase.leader_misses[contender] += 1;
+                self.psel = match contender {
+                    0 => (self.psel + 1).min(self.psel_max),
+                    _ => self.psel.saturating_sub(1),
+                };
+            }
+            None => phase.follower_fills[follower_choice] += 1,
+        }
+    }
+
+    pub(crate) fn phases(&self) -> &[DuelPhase<F>] {
+        &self.phases
+    }
+}
+
+// how the duel went for a stretch of accesses
+//
+// the arrays line up with contenders (so leader_misses()[0] is how often the first contender's
+// leader sets missed)
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DuelPhase<F> {
+    contenders: [F; 2],
+    accesses: u64,
+    leader_misses: [u64; 2],
+    // misses in follower sets, by the contender the followers were using at the time
+    follower_fills: [u64; 2],
+}
+
+impl<F: Copy> DuelPhase<F> {
+    pub fn contenders(&self) -> [F; 2] {
+        self.contenders
+    }
+
+    pub fn accesses(&self) -> u64 {
+        self.accesses
+    }
+
+    pub fn leader_misses(&self) -> [u64; 2] {
+        self.leader_misses
+    }
+
+    pub fn follower_fills(&self) -> [u64; 2] {
+        self.follower_fills
+    }
+
+    // the contender whose leaders missed less (ties go to the first one, same as PSEL)
+    pub fn winner(&self) -> F {
+        if self.leader_misses[1] < self.leader_misses[0] {
+            self.contenders[1]
+        } else {
+            self.contenders[0]
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use crate::random::Rng;
+
+use super::dueling::{DuelPhase, SetDueling};
+
+// where in the recency order a new line goes
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum InsertionPosition {
+    // like it was just used (what LRU normally does)
+    MostRecent,
+    // like it's the oldest thing in the set (it's the next victim unless it gets hit first)
+    LeastRecent,
+}
+
+// decides where new lines go, separately from which line gets kicked out
+//
+// LruPolicy and FifoPolicy take one of these (see with_insertion), by default everything goes
+// in as most recent
+pub trait InsertionPolicy: std::fmt::Debug {
+    // called once before the cache uses the policy
+    fn init(&mut self, _num_sets: u32, _ways: u32) {}
+
+    // where should a new line in this set go? (called for every fill, prefetches too)
+    fn position(&mut self, set: u32) -> InsertionPosition;
+
+    // a line in this set got hit
+    fn on_hit(&mut self, _set: u32) {}
+
+    // the program accessed this set and it missed or not (see ReplacementPolicy::on_access)
+    fn on_access(&mut self, _set: u32, _miss: bool) {}
+}
+
+// the insertion policies from "Adaptive Insertion Policies for High Performance Caching"
+// (Qureshi et al., ISCA 2007)
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum InsertionFlavor {
+    Mru, // always most recent (plain LRU)
+    Lip, // always least recent
+    Bip, // least recent, but most recent once in a while (epsilon of the time)
+    Dip, // Mru or Bip, whichever is missing less (set dueling)
+}
+
+#[derive(Debug)]
+pub struct AdaptiveInsertion {
+    flavor: InsertionFlavor,
+    epsilon: f64,
+    rng: Rng,
+    // only used by DIP
+    dueling: SetDueling<InsertionFlavor>,
+}
+
+impl AdaptiveInsertion {
+    // epsilon is how often BIP (and DIP when it's using BIP) inserts as most recent
+    // (the paper uses 1/32)
+    pub fn new(flavor: InsertionFlavor, epsilon: f64) -> Self {
+        assert!((0.0..=1.0).contains(&epsilon), "epsilon is a probability");
+
+        AdaptiveInsertion {
+            flavor,
+            epsilon,
+            rng: Rng::new(0),
+            dueling: SetDueling::new(InsertionFlavor::Mru, InsertionFlavor::Bip),
+        }
+    }
+
+    pub fn mru() -> Self {
+        AdaptiveInsertion::new(InsertionFlavor::Mru, 0.0)
+    }
+
+    pub fn lip() -> Self {
+        AdaptiveInsertion::new(InsertionFlavor::Lip, 0.0)
+    }
+
+    pub fn bip(epsilon: f64) -> Self {
+        AdaptiveInsertion::new(InsertionFlavor::Bip, epsilon)
+    }
+
+    pub fn dip(epsilon: f64) -> Self {
+        AdaptiveInsertion::new(InsertionFlavor::Dip, epsilon)
+    }
+
+    // seed for BIP's coin flips (the default seed is 0)
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = Rng::new(seed);
+        self
+    }
+
+    // how many accesses make up one phase in the DIP stats (the default is 10000)
+    pub fn with_phase_length(mut self, phase_length: u64) -> Self {
+        assert!(phase_length > 0);
+        self.dueling.phase_length = phase_length;
+        self
+    }
+
+    // how MRU insertion and BIP did against each other, one entry per phase
+    // (the last phase might not be finished yet)
+    //
+    // empty unless this is DIP
+    pub fn phases(&self) -> &[DuelPhase<InsertionFlavor>] {
+        self.dueling.phases()
+    }
+}
+
+impl InsertionPolicy for AdaptiveInsertion {
+    fn init(&mut self, num_sets: u32, _ways: u32) {
+        self.dueling.init(num_sets);
+    }
+
+    fn position(&mut self, set: u32) -> InsertionPosition {
+        let flavor = match self.flavor {
+            InsertionFlavor::Dip => self.dueling.contender_for(set),
+            flavor => flavor,
+        };
+
+        match flavor {
+            InsertionFlavor::Mru => InsertionPosition::MostRecent,
+            InsertionFlavor::Bip if self.rng.chance(self.epsilon) => InsertionPosition::MostRecent,
+            _ => InsertionPosition::LeastRecent,
+        }
+    }
+
+    fn on_access(&mut self, set: u32, miss: bool) {
+        if self.flavor == InsertionFlavor::Dip {
+            self.dueling.record(set, miss);
+        }
+    }
+}
+
+// lend an insertion policy instead of giving it away, so you can look at it afterwards
+impl<T: InsertionPolicy + ?Sized> InsertionPolicy for &mut T {
+    fn init(&mut self, num_sets: u32, ways: u32) {
+        (**self).init(num_sets, ways)
+    }
+
+    fn position(&mut self, set: u32) -> InsertionPosition {
+        (**self).position(set)
+    }
+
+    fn on_hit(&mut self, set: u32) {
+        (**self).on_hit(set)
+    }
+
+    fn on_access(&mut self, set: u32, miss: bool) {
+        (**self).on_access(set, miss)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bip_mostly_inserts_least_recent() {
+        let mut bip = AdaptiveInsertion::bip(1.0 / 32.0);
+        bip.init(1, 4);
+
+        let most_recent = (0..3200)
+            .filter(|_| bip.position(0) == InsertionPosition::MostRecent)
+            .count();
+        assert!((50..150).contains(&most_recent), "{} of 3200 were most recent", most_recent);
+    }
+
+    #[test]
+    fn test_dip_follows_the_leaders() {
+        // 64 sets means 16 leaders of each kind, in groups of 4 sets
+        let mut dip = AdaptiveInsertion::dip(0.0);
+        dip.init(64, 4);
+        assert_eq!(dip.position(2), InsertionPosition::MostRecent);
+
+        // set 0 leads for MRU insertion, when it keeps missing the followers switch to BIP
+        (0..600).for_each(|_| {
+            dip.position(0);
+            dip.on_access(0, true);
+        });
+        assert_eq!(dip.position(2), InsertionPosition::LeastRecent);
+        assert_eq!(dip.position(0), InsertionPosition::MostRecent);
+        assert_eq!(dip.phases()[0].winner(), InsertionFlavor::Bip);
+    }
+}
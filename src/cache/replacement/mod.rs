@@ -0,0 +1,280 @@
+mod dueling;
+mod insertion;
+mod rrip;
+
+use crate::user_input;
+
+pub use dueling::DuelPhase;
+pub use insertion::{AdaptiveInsertion, InsertionFlavor, InsertionPolicy, InsertionPosition};
+pub use rrip::{RripFlavor, RripPolicy};
+
+// decides which line gets kicked out when a set is full
+//
+// a Cache tells its policy about everything that happens to a line (hits and fills) and asks
+// it for a victim when there is no room left. The policy keeps whatever per-set state it
+// needs, sets and ways are just numbers:
+//  - set is the set index (the set bits of the address as a number, 0 if there is one set)
+//  - way is which line in the set (0 to lines_per_set - 1)
+//
+// LruPolicy, FifoPolicy (with any InsertionPolicy) and the RRIP family are the ones you get from
+// the prompts, but you can write your own and give it to a cache with
+// Cache::set_replacement_policy
+pub trait ReplacementPolicy: std::fmt::Debug {
+    // called once before the cache uses the policy
+    fn init(&mut self, num_sets: u32, ways: u32);
+
+    // the line at (set, way) was already in the cache and got accessed again
+    fn on_hit(&mut self, set: u32, way: u32);
+
+    // a new line was put in at (set, way)
+    //
+    // if the set was full, way is whatever choose_victim picked. Prefetched lines get filled
+    // too, so this isn't the same as a miss (see on_access)
+    fn on_fill(&mut self, set: u32, way: u32);
+
+    // the program (not the prefetcher) accessed this set, and it missed or not (a sector miss
+    // is a miss, see Cache::set_sectors). Comes after on_hit or on_fill, for policies that keep
+    // score of misses (like set dueling)
+    fn on_access(&mut self, _set: u32, _miss: bool) {}
+
+    // the set is full, which way should be replaced?
+    fn choose_victim(&mut self, set: u32) -> u32;
+
+    // with skewed indexing (see IndexFunction::Skewed) every way of a line is in a different
+    // set, so candidates has one (set, way) per way and the victim is one of them (return its
+    // way)
+    //
+    // by default every candidate's set gets asked for its victim, and the first candidate that
+    // its own set would kick out goes (or the first candidate if none of them would). Policies
+    // that can compare lines across sets should do better (LRU, FIFO and RRIP do)
+    fn choose_skewed_victim(&mut self, candidates: &[(u32, u32)]) -> u32 {
+        candidates
+            .iter()
+            .find(|(set, way)| self.choose_victim(*set) == *way)
+            .unwrap_or(&candidates[0])
+            .1
+    }
+}
+
+// lend a policy to a cache instead of giving it away, so you can look at it afterwards
+// (e.g. cache.set_replacement_policy(Box::new(&mut drrip)) and then drrip.phases())
+impl<T: ReplacementPolicy + ?Sized> ReplacementPolicy for &mut T {
+    fn init(&mut self, num_sets: u32, ways: u32) {
+        (**self).init(num_sets, ways)
+    }
+
+    fn on_hit(&mut self, set: u32, way: u32) {
+        (**self).on_hit(set, way)
+    }
+
+    fn on_fill(&mut self, set: u32, way: u32) {
+        (**self).on_fill(set, way)
+    }
+
+    fn on_access(&mut self, set: u32, miss: bool) {
+        (**self).on_access(set, miss)
+    }
+
+    fn choose_victim(&mut self, set: u32) -> u32 {
+        (**self).choose_victim(set)
+    }
+
+    fn choose_skewed_victim(&mut self, candidates: &[(u32, u32)]) -> u32 {
+        (**self).choose_skewed_victim(candidates)
+    }
+}
+
+// the policy the user asked for at the prompts
+//
+// the RRIP family gets 2 bit RRPVs and BIP and DIP get an epsilon of 1/32 (build the policy
+// yourself for anything else)
+pub fn from_user_input(policy: user_input::ReplacementPolicy) -> Box<dyn ReplacementPolicy> {
+    match policy {
+        user_input::ReplacementPolicy::LIP => Box::new(LruPolicy::with_insertion(Box::new(AdaptiveInsertion::lip()))),
+        user_input::ReplacementPolicy::BIP => Box::new(LruPolicy::with_insertion(Box::new(AdaptiveInsertion::bip(1.0 / 32.0)))),
+        user_input::ReplacementPolicy::DIP => Box::new(LruPolicy::with_insertion(Box::new(AdaptiveInsertion::dip(1.0 / 32.0)))),
+        user_input::ReplacementPolicy::LRU => Box::new(LruPolicy::default()),
+        user_input::ReplacementPolicy::FIFO => Box::new(FifoPolicy::default()),
+        user_input::ReplacementPolicy::SRRIP => Box::new(RripPolicy::srrip(2)),
+        user_input::ReplacementPolicy::BRRIP => Box::new(RripPolicy::brrip(2)),
+        user_input::ReplacementPolicy::DRRIP => Box::new(RripPolicy::drrip(2)),
+    }
+}
+
+// which RRIP flavor a policy from the prompts is (None if it isn't SRRIP, BRRIP or DRRIP), e.g.
+// to build it with other RRPV widths
+pub fn rrip_flavor(policy: user_input::ReplacementPolicy) -> Option<RripFlavor> {
+    match policy {
+        user_input::ReplacementPolicy::SRRIP => Some(RripFlavor::Static),
+        user_input::ReplacementPolicy::BRRIP => Some(RripFlavor::Bimodal),
+        user_input::ReplacementPolicy::DRRIP => Some(RripFlavor::Dynamic),
+        _ => None,
+    }
+}
+
+// every line gets a timestamp, the line with the oldest one is the victim
+//
+// both LRU and FIFO are this with a different idea of when to update the timestamp
+#[derive(Debug, Default)]
+struct Timestamps {
+    ways: u32,
+    clock: i64,
+    // one per line, set by set (so set 1 way 0 is at index ways), None for empty lines
+    stamps: Vec<Option<i64>>,
+}
+
+impl Timestamps {
+    fn init(&mut self, num_sets: u32, ways: u32) {
+        self.ways = ways;
+        self.clock = 0;
+        self.stamps = vec![None; num_sets as usize * ways as usize];
+    }
+
+    fn set_stamps(&self, set: u32) -> &[Option<i64>] {
+        let start = (set * self.ways) as usize;
+        &self.stamps[start..start + self.ways as usize]
+    }
+
+    // give a line a timestamp
+    //
+    // least recent means older than every other line in the set
+    fn insert(&mut self, set: u32, way: u32, position: InsertionPosition) {
+        let stamp = match position {
+            InsertionPosition::MostRecent => {
+                self.clock += 1;
+                self.clock
+            }
+            InsertionPosition::LeastRecent => {
+                let others = self
+                    .set_stamps(set)
+                    .iter()
+                    .enumerate()
+                    .filter(|(other_way, _)| *other_way as u32 != way)
+                    .filter_map(|(_, stamp)| *stamp);
+                others.min().map_or(self.clock, |oldest| oldest - 1)
+            }
+        };
+        self.stamps[(set * self.ways + way) as usize] = Some(stamp);
+    }
+
+    fn touch(&mut self, set: u32, way: u32) {
+        self.insert(set, way, InsertionPosition::MostRecent);
+    }
+
+    fn oldest(&self, set: u32) -> u32 {
+        let set_stamps = self.set_stamps(set);
+
+        (0..self.ways)
+            .min_by_key(|way| set_stamps[*way as usize])
+            .expect("sets have at least one line")
+    }
+
+    // the way of the oldest line out of some (set, way)s
+    fn oldest_of(&self, candidates: &[(u32, u32)]) -> u32 {
+        candidates
+            .iter()
+            .min_by_key(|(set, way)| self.stamps[(set * self.ways + way) as usize])
+            .expect("there's at least one candidate")
+            .1
+    }
+}
+
+// least recently used: hits and fills both count as a use
+//
+// new lines go in as most recent unless you give it some other InsertionPolicy
+// (e.g. LruPolicy::with_insertion(Box::new(AdaptiveInsertion::dip(1.0 / 32.0))) is DIP)
+#[derive(Debug)]
+pub struct LruPolicy<'a> {
+    timestamps: Timestamps,
+    insertion: Box<dyn InsertionPolicy + 'a>,
+}
+
+impl<'a> LruPolicy<'a> {
+    pub fn with_insertion(insertion: Box<dyn InsertionPolicy + 'a>) -> Self {
+        LruPolicy { timestamps: Timestamps::default(), insertion }
+    }
+}
+
+impl Default for LruPolicy<'_> {
+    fn default() -> Self {
+        LruPolicy::with_insertion(Box::new(AdaptiveInsertion::mru()))
+    }
+}
+
+impl ReplacementPolicy for LruPolicy<'_> {
+    fn init(&mut self, num_sets: u32, ways: u32) {
+        self.timestamps.init(num_sets, ways);
+        self.insertion.init(num_sets, ways);
+    }
+
+    fn on_hit(&mut self, set: u32, way: u32) {
+        self.timestamps.touch(set, way);
+        self.insertion.on_hit(set);
+    }
+
+    fn on_fill(&mut self, set: u32, way: u32) {
+        let position = self.insertion.position(set);
+        self.timestamps.insert(set, way, position);
+    }
+
+    fn on_access(&mut self, set: u32, miss: bool) {
+        self.insertion.on_access(set, miss);
+    }
+
+    fn choose_victim(&mut self, set: u32) -> u32 {
+        self.timestamps.oldest(set)
+    }
+
+    fn choose_skewed_victim(&mut self, candidates: &[(u32, u32)]) -> u32 {
+        self.timestamps.oldest_of(candidates)
+    }
+}
+
+// first in first out: only fills count, hits don't matter
+//
+// takes an InsertionPolicy too (least recent means "next out" instead of "last out")
+#[derive(Debug)]
+pub struct FifoPolicy<'a> {
+    timestamps: Timestamps,
+    insertion: Box<dyn InsertionPolicy + 'a>,
+}
+
+impl<'a> FifoPolicy<'a> {
+    pub fn with_insertion(insertion: Box<dyn InsertionPolicy + 'a>) -> Self {
+        FifoPolicy { timestamps: Timestamps::default(), insertion }
+    }
+}
+
+impl Default for FifoPolicy<'_> {
+    fn default() -> Self {
+        FifoPolicy::with_insertion(Box::new(AdaptiveInsertion::mru()))
+    }
+}
+
+impl ReplacementPolicy for FifoPolicy<'_> {
+    fn init(&mut self, num_sets: u32, ways: u32) {
+        self.timestamps.init(num_sets, ways);
+        self.insertion.init(num_sets, ways);
+    }
+
+    fn on_hit(&mut self, set: u32, _way: u32) {
+        self.insertion.on_hit(set);
+    }
+
+    fn on_fill(&mut self, set: u32, way: u32) {
+        let position = self.insertion.position(set);
+        self.timestamps.insert(set, way, position);
+    }
+
+    fn on_access(&mut self, set: u32, miss: bool) {
+        self.insertion.on_access(set, miss);
+    }
+
+    fn choose_victim(&mut self, set: u32) -> u32 {
+        self.timestamps.oldest(set)
+    }
+
+    fn choose_skewed_victim(&mut self, candidates: &[(u32, u32)]) -> u32 {
+        self.timestamps.oldest_of(candidates)
+    }
+}
@@ -0,0 +1,214 @@
+use crate::random::Rng;
+
+use super::dueling::{DuelPhase, SetDueling};
+use super::ReplacementPolicy;
+
+// re-reference interval prediction (Jaleel et al., ISCA 2010)
+//
+// every line gets an RRPV (re-reference prediction value), a small counter that guesses how
+// far in the future the line will be used again:
+//  - 0 means "soon", the max (2^bits - 1) means "not any time soon"
+//  - a hit sets it to 0
+//  - the victim is a line at the max, if there isn't one every line gets older until there is
+//
+// the flavors only differ in what RRPV a new line gets:
+//  - SRRIP: max - 1, so a line has to prove itself before it's safe (resists scans)
+//  - BRRIP: max most of the time, max - 1 once in a while (resists thrashing)
+//  - DRRIP: SRRIP or BRRIP, whichever is missing less (set dueling, see dueling.rs)
+#[derive(Debug)]
+pub struct RripPolicy {
+    flavor: RripFlavor,
+    max_rrpv: u8,
+    // how often BRRIP inserts at max - 1 instead of max
+    bimodal_chance: f64,
+    rng: Rng,
+    ways: u32,
+    // one per line, set by set (so set 1 way 0 is at index ways)
+    rrpvs: Vec<u8>,
+    // only used by DRRIP
+    dueling: SetDueling<RripFlavor>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RripFlavor {
+    Static,  // SRRIP
+    Bimodal, // BRRIP
+    Dynamic, // DRRIP
+}
+
+impl RripPolicy {
+    // rrpv_bits is how wide the RRPV counters are (2 is what the paper recommends)
+    pub fn new(flavor: RripFlavor, rrpv_bits: u8) -> Self {
+        assert!((1..=8).contains(&rrpv_bits), "RRPVs have to be 1 to 8 bits");
+
+        RripPolicy {
+            flavor,
+            max_rrpv: ((1u16 << rrpv_bits) - 1) as u8,
+            bimodal_chance: 1.0 / 32.0,
+            rng: Rng::new(0),
+            ways: 0,
+            rrpvs: Vec::new(),
+            dueling: SetDueling::new(RripFlavor::Static, RripFlavor::Bimodal),
+        }
+    }
+
+    pub fn srrip(rrpv_bits: u8) -> Self {
+        RripPolicy::new(RripFlavor::Static, rrpv_bits)
+    }
+
+    pub fn brrip(rrpv_bits: u8) -> Self {
+        RripPolicy::new(RripFlavor::Bimodal, rrpv_bits)
+    }
+
+    pub fn drrip(rrpv_bits: u8) -> Self {
+        RripPolicy::new(RripFlavor::Dynamic, rrpv_bits)
+    }
+
+    // seed for BRRIP's coin flips (the default seed is 0)
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = Rng::new(seed);
+        self
+    }
+
+    // how many accesses make up one phase in the DRRIP stats (the default is 10000)
+    pub fn with_phase_length(mut self, phase_length: u64) -> Self {
+        assert!(phase_length > 0);
+        self.dueling.phase_length = phase_length;
+        self
+    }
+
+    // how SRRIP and BRRIP did against each other, one entry per phase
+    // (the last phase might not be finished yet)
+    //
+    // empty unless this is DRRIP
+    pub fn phases(&self) -> &[DuelPhase<RripFlavor>] {
+        self.dueling.phases()
+    }
+
+    fn rrpv(&mut self, set: u32, way: u32) -> &mut u8 {
+        &mut self.rrpvs[(set * self.ways + way) as usize]
+    }
+
+    // the flavor that decides what RRPV a new line in this set gets
+    fn insertion_flavor(&self, set: u32) -> RripFlavor {
+        match self.flavor {
+            RripFlavor::Dynamic => self.dueling.contender_for(set),
+            flavor => flavor,
+        }
+    }
+}
+
+impl ReplacementPolicy for RripPolicy {
+    fn init(&mut self, num_sets: u32, ways: u32) {
+        self.ways = ways;
+        // empty lines are as far away as it gets
+        self.rrpvs = vec![self.max_rrpv; num_sets as usize * ways as usize];
+        self.dueling.init(num_sets);
+    }
+
+    fn on_hit(&mut self, set: u32, way: u32) {
+        *self.rrpv(set, way) = 0;
+    }
+
+    fn on_fill(&mut self, set: u32, way: u32) {
+        let rrpv = match self.insertion_flavor(set) {
+            RripFlavor::Bimodal if !self.rng.chance(self.bimodal_chance) => self.max_rrpv,
+            _ => self.max_rrpv - 1,
+        };
+        *self.rrpv(set, way) = rrpv;
+    }
+
+    fn on_access(&mut self, set: u32, miss: bool) {
+        if self.flavor == RripFlavor::Dynamic {
+            self.dueling.record(set, miss);
+        }
+    }
+
+    fn choose_victim(&mut self, set: u32) -> u32 {
+        let start = (set * self.ways) as usize;
+        let set_rrpvs = &mut self.rrpvs[start..start + self.ways as usize];
+
+        // age everyone until someone is at the max (all at once instead of one step at a time)
+        let oldest = *set_rrpvs.iter().max().expect("sets have at least one line");
+        let age = self.max_rrpv - oldest;
+        set_rrpvs.iter_mut().for_each(|rrpv| *rrpv += age);
+
+        set_rrpvs
+            .iter()
+            .position(|rrpv| *rrpv == self.max_rrpv)
+            .unwrap() as u32
+    }
+
+    // the same thing, but only the candidates get older (they're the only lines the new one
+    // could replace)
+    fn choose_skewed_victim(&mut self, candidates: &[(u32, u32)]) -> u32 {
+        let ways = self.ways;
+        let index = |(set, way): &(u32, u32)| (set * ways + way) as usize;
+
+        let oldest = candidates.iter().map(|candidate| self.rrpvs[index(candidate)]).max().expect("there's at least one candidate");
+        let age = self.max_rrpv - oldest;
+        candidates.iter().for_each(|candidate| self.rrpvs[index(candidate)] += age);
+
+        candidates
+            .iter()
+            .find(|candidate| self.rrpvs[index(candidate)] == self.max_rrpv)
+            .unwrap()
+            .1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_srrip_survives_a_scan() {
+        // one set, 4 ways
+        let mut srrip = RripPolicy::srrip(2);
+        srrip.init(1, 4);
+
+        // ways 0 and 1 get hit (RRPV 0), ways 2 and 3 are a scan (RRPV 2)
+        (0..4).for_each(|way| srrip.on_fill(0, way));
+        srrip.on_hit(0, 0);
+        srrip.on_hit(0, 1);
+
+        // the scan lines go first
+        let victim = srrip.choose_victim(0);
+        assert_eq!(victim, 2);
+        srrip.on_fill(0, victim);
+        assert_eq!(srrip.choose_victim(0), 3);
+    }
+
+    #[test]
+    fn test_skewed_victim_comes_from_the_candidates() {
+        // 4 sets of 2 ways, the candidates are set 1 way 0 and set 3 way 1
+        let mut srrip = RripPolicy::srrip(2);
+        srrip.init(4, 2);
+        (0..4).for_each(|set| (0..2).for_each(|way| srrip.on_fill(set, way)));
+        srrip.on_hit(1, 0);
+
+        // only set 3 way 1 was never hit, and lines that aren't candidates don't get older
+        assert_eq!(srrip.choose_skewed_victim(&[(1, 0), (3, 1)]), 1);
+        assert_eq!((srrip.rrpvs[2], srrip.rrpvs[7], srrip.rrpvs[6]), (1, 3, 2));
+    }
+
+    #[test]
+    fn test_drrip_picks_brrip_when_srrip_leaders_miss() {
+        // 64 sets means 16 leaders of each kind, in groups of 4 sets
+        let mut drrip = RripPolicy::drrip(2).with_phase_length(100);
+        drrip.init(64, 1);
+        assert_eq!(drrip.insertion_flavor(2), RripFlavor::Static);
+
+        // set 0 is an SRRIP leader, set 1 a BRRIP leader
+        (0..600).for_each(|_| {
+            drrip.on_fill(0, 0);
+            drrip.on_access(0, true);
+        });
+        assert_eq!(drrip.insertion_flavor(2), RripFlavor::Bimodal);
+        assert_eq!(drrip.insertion_flavor(0), RripFlavor::Static);
+        assert_eq!(drrip.insertion_flavor(1), RripFlavor::Bimodal);
+
+        assert_eq!(drrip.phases().len(), 6);
+        assert!(drrip.phases().iter().all(|phase| phase.winner() == RripFlavor::Bimodal));
+    }
+}
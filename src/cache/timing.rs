@@ -0,0 +1,155 @@
+use super::SimResults;
+
+// how many cycles everything takes (see SimResults::timing)
+//
+// every access pays hit_latency (plus its translation, with an Mmu). A miss also pays
+// miss_penalty, and then victim_hit_latency if the line was in the victim cache or
+// memory_latency if it wasn't. Writebacks and prefetches are free (think write buffer and
+// spare bandwidth).
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Latencies {
+    pub hit_latency: u32,
+    // on top of where the line comes from (e.g. the time to notice the miss and fill the line)
+    pub miss_penalty: u32,
+    pub memory_latency: u32,
+    pub victim_hit_latency: u32,
+    // for every TLB level that gets looked at (tlb_latencies[0] is the first level). Levels past
+    // the end cost the same as the last one
+    pub tlb_latencies: Vec<u32>,
+    // for every memory reference a page table walk makes
+    pub walk_reference_latency: u32,
+}
+
+impl Default for Latencies {
+    fn default() -> Self {
+        Latencies {
+            hit_latency: 1,
+            miss_penalty: 0,
+            memory_latency: 100,
+            victim_hit_latency: 1,
+            tlb_latencies: vec![1, 7],
+            walk_reference_latency: 100,
+        }
+    }
+}
+
+impl Latencies {
+    fn tlb_latency(&self, level: usize) -> u32 {
+        self.tlb_latencies
+            .get(level)
+            .or(self.tlb_latencies.last())
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+// how long a simulation took
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Timing {
+    pub total_cycles: u64,
+    // cycles spent on anything but hit_latency (what a perfect cache wouldn't have to wait for)
+    pub stall_cycles: u64,
+    // average memory access time, total_cycles / accesses (0 with no accesses)
+    pub amat: f64,
+}
+
+impl SimResults {
+    // how many cycles this would have taken with these latencies
+    //
+    // everything the latencies depend on is counted in the results anyway, so any number of
+    // latency setups can be tried on one simulation
+    pub fn timing(&self, latencies: &Latencies) -> Timing {
+        let memory_misses = (self.misses - self.victim_hits) as u64;
+
+        let translation_cycles: u64 = self
+            .tlb
+            .hits
+            .iter()
+            .zip(&self.tlb.misses)
+            .enumerate()
+            .map(|(level, (hits, misses))| (hits + misses) as u64 * latencies.tlb_latency(level) as u64)
+            .sum::<u64>()
+            + self.tlb.walk_references as u64 * latencies.walk_reference_latency as u64;
+
+        let hit_cycles = self.accesses as u64 * latencies.hit_latency as u64;
+        let stall_cycles = translation_cycles
+            + self.misses as u64 * latencies.miss_penalty as u64
+            + self.victim_hits as u64 * latencies.victim_hit_latency as u64
+            + memory_misses * latencies.memory_latency as u64;
+
+        let total_cycles = hit_cycles + stall_cycles;
+        let amat = match self.accesses {
+            0 => 0.0,
+            accesses => total_cycles as f64 / accesses as f64,
+        };
+
+        Timing { total_cycles, stall_cycles, amat }
+    }
+}
+
+// AMAT for caches stacked on top of each other (L1 first), where every level's misses go down
+// to the next one and the last level's go to memory:
+//
+// L1 hit + L1 miss rate * (L1 miss penalty + L2 hit + L2 miss rate * (... + memory latency))
+//
+// miss rates are local (misses / accesses at that level), misses a victim cache catches cost
+// victim_hit_latency instead of going down, and memory_latency comes from the last level. Below
+// the first level only loads count, the stores there are writebacks from the level above and
+// nobody waits for those (SimResults::loads and friends). With one level this is the same AMAT
+// as SimResults::timing (minus translation, which is only for an L1 with an Mmu)
+pub fn hierarchy_amat(levels: &[(&SimResults, &Latencies)]) -> f64 {
+    let memory_latency = levels.last().map_or(0, |(_, latencies)| latencies.memory_latency) as f64;
+
+    levels.iter().enumerate().rev().fold(memory_latency, |below, (level, (results, latencies))| {
+        let (accesses, misses, victim_hits) = match level {
+            0 => (results.accesses, results.misses, results.victim_hits),
+            _ => (results.loads, results.load_misses, results.load_victim_hits),
+        };
+        if accesses == 0 {
+            return 0.0;
+        }
+        let per_access = |count: u32| count as f64 / accesses as f64;
+
+        latencies.hit_latency as f64
+            + per_access(misses) * latencies.miss_penalty as f64
+            + per_access(victim_hits) * latencies.victim_hit_latency as f64
+            + per_access(misses - victim_hits) * below
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::TlbStats;
+
+    #[test]
+    fn test_amat() {
+        // 10 accesses, 2 misses (1 of them caught by the victim cache)
+        let results = SimResults { hits: 8, misses: 2, accesses: 10, victim_hits: 1, ..Default::default() };
+        let latencies = Latencies { miss_penalty: 2, ..Default::default() };
+
+        // 10 * 1 + 2 * 2 + 1 + 100
+        let timing = results.timing(&latencies);
+        assert_eq!((timing.total_cycles, timing.stall_cycles), (115, 105));
+        assert_eq!(timing.amat, 11.5);
+
+        // every access looks at the L1 TLB, 3 of them miss, go to the L2 and 1 walks (2 levels)
+        let tlb = TlbStats { translations: 10, hits: vec![7, 2], misses: vec![3, 1], walks: 1, walk_references: 2 };
+        let results = SimResults { tlb, ..results };
+        assert_eq!(results.timing(&latencies).stall_cycles, 105 + 10 + 3 * 7 + 200);
+    }
+
+    #[test]
+    fn test_hierarchy_amat() {
+        let l1 = SimResults { misses: 10, accesses: 100, ..Default::default() };
+        // 10 loads from the L1's misses (5 of them miss) and 6 writebacks that all miss
+        let l2 = SimResults { misses: 11, accesses: 16, loads: 10, load_misses: 5, ..Default::default() };
+        let l1_latencies = Latencies { hit_latency: 4, ..Default::default() };
+        let l2_latencies = Latencies { hit_latency: 12, memory_latency: 200, ..Default::default() };
+
+        // 4 + 0.1 * (12 + 0.5 * 200), the writebacks don't count
+        assert!((hierarchy_amat(&[(&l1, &l1_latencies), (&l2, &l2_latencies)]) - 15.2).abs() < 1e-9);
+        // one level is the same as timing
+        assert!((hierarchy_amat(&[(&l1, &l1_latencies)]) - l1.timing(&l1_latencies).amat).abs() < 1e-9);
+    }
+}
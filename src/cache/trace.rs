@@ -0,0 +1,258 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+
+use super::AccessKind;
+
+// one line of a trace file, e.g. "l 0x0000AA40 1"
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Access {
+    pub kind: AccessKind,
+    pub address: u32,
+    pub size: u32, // in bytes (the simulator doesn't use it, but the file format has it)
+}
+
+// the size of accesses in traces that only have l/s and the address
+pub const DEFAULT_ACCESS_SIZE: u32 = 4;
+
+// parse one line of a trace file
+//
+// first char is either l or s, then a hex address starting with 0x (8 digits or less),
+// then a decimal number (the size, which older traces leave out, see DEFAULT_ACCESS_SIZE)
+impl std::str::FromStr for Access {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let line = line.trim().to_lowercase();
+        let mut line_iter = line.split_whitespace();
+
+        let kind = AccessKind::try_from(line_iter.next().unwrap_or(""))?;
+
+        let hex_addy = line_iter
+            .next()
+            .ok_or("missing an address")?
+            .strip_prefix("0x")
+            .ok_or("hex addresses should start with 0x")?;
+        // some traces (write01.trace) have 7 digit addresses, they just mean a leading 0
+        if hex_addy.len() > 8 {
+            return Err(format!("addresses are 32 bits, got 0x{}", hex_addy));
+        }
+        let address = u32::from_str_radix(hex_addy, 16).map_err(|e| format!("0x{} is not a hex address: {}", hex_addy, e))?;
+
+        let size = match line_iter.next() {
+            Some(size) => size.parse().map_err(|e| format!("bad access size: {}", e))?,
+            None => DEFAULT_ACCESS_SIZE,
+        };
+
+        Ok(Access { kind, address, size })
+    }
+}
+
+// write an access the same way the trace files do
+impl std::fmt::Display for Access {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ls = match self.kind {
+            AccessKind::Load => "l",
+            AccessKind::Store => "s",
+        };
+        write!(f, "{} 0x{:08X} {}", ls, self.address, self.size)
+    }
+}
+
+// anything the simulator can get accesses from (see Cache::simulate)
+//
+// there are sources for files, stdin, vectors and generators below, but anything that can
+// hand out accesses one at a time works
+pub trait TraceSource {
+    // the next access, or None when the trace is over
+    fn next_access(&mut self) -> Option<Access>;
+}
+
+impl<T: TraceSource + ?Sized> TraceSource for Box<T> {
+    fn next_access(&mut self) -> Option<Access> {
+        (**self).next_access()
+    }
+}
+
+// so a source can be lent out and still asked about afterwards (e.g. ReaderSource::error)
+impl<T: TraceSource + ?Sized> TraceSource for &mut T {
+    fn next_access(&mut self) -> Option<Access> {
+        (**self).next_access()
+    }
+}
+
+// reads a trace one line at a time (so huge traces don't have to fit in memory)
+//
+// blank lines are skipped. The trace ends at the first line that doesn't follow the trace
+// format (or that can't be read), so check error afterwards
+#[derive(Debug)]
+pub struct ReaderSource<R> {
+    reader: R,
+    name: String, // for error messages
+    line_number: usize,
+    // what made the trace end early (see error)
+    error: Option<String>,
+}
+
+impl<R: BufRead> ReaderSource<R> {
+    pub fn new(reader: R, name: &str) -> Self {
+        ReaderSource { reader, name: name.into(), line_number: 0, error: None }
+    }
+
+    // what made the trace end early, e.g. "some.trace line 12: missing an address" (None if it
+    // didn't)
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+}
+
+impl ReaderSource<BufReader<File>> {
+    pub fn open(filepath: &str) -> io::Result<Self> {
+        Ok(ReaderSource::new(BufReader::new(File::open(filepath)?), filepath))
+    }
+}
+
+impl ReaderSource<io::StdinLock<'static>> {
+    // e.g. zcat huge.trace | cargo run -- -
+    pub fn stdin() -> Self {
+        ReaderSource::new(io::stdin().lock(), "stdin")
+    }
+}
+
+impl<R: BufRead> ReaderSource<R> {
+    // read and parse the next line that isn't blank
+    //
+    // other line formats (like the multi core one in coherence) use this too
+    pub(crate) fn next_parsed<T: std::str::FromStr<Err = String>>(&mut self) -> Option<T> {
+        if self.error.is_some() {
+            return None;
+        }
+
+        let mut line = String::new();
+        loop {
+            line.clear();
+            self.line_number += 1;
+
+            match self.reader.read_line(&mut line) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(e) => {
+                    self.error = Some(format!("could not read {}: {}", self.name, e));
+                    return None;
+                }
+            }
+
+            if !line.trim().is_empty() {
+                break;
+            }
+        }
+
+        match line.parse() {
+            Ok(parsed) => Some(parsed),
+            Err(e) => {
+                self.error = Some(format!("{}: {}", self.location(), e));
+                None
+            }
+        }
+    }
+
+    // where the last line came from, for error messages (e.g. "some.trace line 12")
+    pub(crate) fn location(&self) -> String {
+        format!("{} line {}", self.name, self.line_number)
+    }
+}
+
+impl<R: BufRead> TraceSource for ReaderSource<R> {
+    fn next_access(&mut self) -> Option<Access> {
+        self.next_parsed()
+    }
+}
+
+// open a trace file, or stdin if the path is "-"
+pub fn open(filepath: &str) -> io::Result<ReaderSource<Box<dyn BufRead>>> {
+    if filepath == "-" {
+        Ok(ReaderSource::new(Box::new(io::stdin().lock()), "stdin"))
+    } else {
+        Ok(ReaderSource::new(Box::new(BufReader::new(File::open(filepath)?)), filepath))
+    }
+}
+
+// a trace that is already in memory
+#[derive(Debug, Default)]
+pub struct VecSource {
+    accesses: std::vec::IntoIter<Access>,
+}
+
+impl From<Vec<Access>> for VecSource {
+    fn from(accesses: Vec<Access>) -> Self {
+        VecSource { accesses: accesses.into_iter() }
+    }
+}
+
+impl TraceSource for VecSource {
+    fn next_access(&mut self) -> Option<Access> {
+        self.accesses.next()
+    }
+}
+
+// a trace made up on the spot, by calling a function until it returns None
+//
+// e.g. 1000 loads walking through memory 64 bytes at a time:
+// let mut address = 0;
+// GeneratorSource::new(move || {
+//     address += 64;
+//     (address <= 64000).then_some(Access { kind: AccessKind::Load, address, size: 4 })
+// })
+pub struct GeneratorSource<F> {
+    generator: F,
+}
+
+impl<F: FnMut() -> Option<Access>> GeneratorSource<F> {
+    pub fn new(generator: F) -> Self {
+        GeneratorSource { generator }
+    }
+}
+
+impl<F: FnMut() -> Option<Access>> TraceSource for GeneratorSource<F> {
+    fn next_access(&mut self) -> Option<Access> {
+        (self.generator)()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_write_access() {
+        let access: Access = "S 0x0000aa40 12".parse().unwrap();
+        assert_eq!(access, Access { kind: AccessKind::Store, address: 0xAA40, size: 12 });
+        assert_eq!(access.to_string(), "s 0x0000AA40 12");
+
+        // 7 digits is fine
+        let access: Access = "l 0x000AA40 1".parse().unwrap();
+        assert_eq!(access.address, 0xAA40);
+
+        assert!("x 0x0000AA40 1".parse::<Access>().is_err());
+        assert!("l 0000AA40 1".parse::<Access>().is_err());
+        assert!("l 0x100000000 1".parse::<Access>().is_err());
+        // older traces don't have sizes
+        assert_eq!("l 0x0000AA40".parse::<Access>().unwrap().size, DEFAULT_ACCESS_SIZE);
+        assert!("l 0x0000AA40 big".parse::<Access>().is_err());
+    }
+
+    #[test]
+    fn test_reader_source_skips_blank_lines() {
+        let mut source = ReaderSource::new("l 0x00000010 1\n\ns 0x00000020 4\n".as_bytes(), "test");
+        assert_eq!(source.next_access().map(|a| a.address), Some(0x10));
+        assert_eq!(source.next_access().map(|a| a.address), Some(0x20));
+        assert_eq!(source.next_access(), None);
+        assert_eq!(source.error(), None);
+
+        // a bad line ends the trace, and says where it was
+        let mut source = ReaderSource::new("l 0x00000010 1\nx 0x00000020 4\nl 0x00000030 1\n".as_bytes(), "test");
+        assert!(source.next_access().is_some());
+        assert_eq!(source.next_access(), None);
+        assert_eq!(source.next_access(), None);
+        assert!(source.error().unwrap().starts_with("test line 2: "), "{:?}", source.error());
+    }
+}
@@ -0,0 +1,53 @@
+// a small fully associative cache that catches lines kicked out of the main cache
+// (Jouppi, ISCA 1990)
+//
+// misses in the main cache check here before going to memory. A line found here gets swapped
+// back into the main cache (and whatever that kicks out takes its place here), so two lines
+// fighting over the same set in a direct mapped cache stop missing all the time.
+//
+// lines are kept from least to most recently put in, and the least recent one leaves when
+// there's no room
+#[derive(Debug)]
+pub(crate) struct VictimCache {
+    capacity: usize,
+    // (line address, valid sectors, dirty sectors), see Line
+    lines: Vec<(u32, u64, u64)>,
+}
+
+impl VictimCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "a victim cache needs at least one line");
+        VictimCache { capacity, lines: Vec::with_capacity(capacity) }
+    }
+
+    pub(crate) fn contains(&self, line_address: u32) -> bool {
+        self.lines.iter().any(|(address, _, _)| *address == line_address)
+    }
+
+    // take a line out (for swapping it back into the main cache), Some((valid sectors, dirty
+    // sectors)) if it was here
+    pub(crate) fn take(&mut self, line_address: u32) -> Option<(u64, u64)> {
+        let i = self.lines.iter().position(|(address, _, _)| *address == line_address)?;
+        let (_, valid_sectors, dirty_sectors) = self.lines.remove(i);
+        Some((valid_sectors, dirty_sectors))
+    }
+
+    // put a line kicked out of the main cache in
+    //
+    // if it's full, the line that has been here longest leaves for good (and is returned, so it
+    // can be written back if it's dirty)
+    pub(crate) fn insert(&mut self, line_address: u32, valid_sectors: u64, dirty_sectors: u64) -> Option<(u32, u64, u64)> {
+        let leaving = if self.lines.len() == self.capacity {
+            Some(self.lines.remove(0))
+        } else {
+            None
+        };
+
+        self.lines.push((line_address, valid_sectors, dirty_sectors));
+        leaving
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.lines.clear();
+    }
+}
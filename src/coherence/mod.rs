@@ -0,0 +1,272 @@
+mod protocol;
+mod sharing;
+pub mod trace;
+
+use std::collections::{HashMap, HashSet};
+
+use crate::cache::{Access, AccessKind, Cache};
+use crate::user_input::UserInput;
+
+pub use protocol::{Protocol, State};
+pub use sharing::{LineReport, SharingDetector};
+pub use trace::{CoreAccess, CoreTraceSource};
+
+// what happened on one core (see MultiCore::simulate)
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct CoreStats {
+    pub accesses: u32,
+    pub hits: u32,
+    pub misses: u32,
+    // misses on lines that were only gone because another core invalidated them
+    pub coherence_misses: u32,
+    // times another core's write threw out one of this core's lines
+    pub invalidations: u32,
+    // times this core had to answer another core's request (it had the line M, O or E)
+    pub interventions: u32,
+    // bus transactions this core started
+    pub bus_reads: u32,            // BusRd, read misses
+    pub bus_read_exclusives: u32,  // BusRdX, write misses
+    pub bus_upgrades: u32,         // BusUpgr, writes to lines it already had Shared or Owned
+    pub writebacks: u32,           // dirty lines written back (kicked out, or snooped in MSI/MESI)
+}
+
+impl CoreStats {
+    pub fn bus_transactions(&self) -> u32 {
+        self.bus_reads + self.bus_read_exclusives + self.bus_upgrades + self.writebacks
+    }
+}
+
+// one core's private cache, plus the coherence state of everything in it
+#[derive(Debug)]
+struct Core<'a> {
+    cache: Cache<'a>,
+    // lines that aren't in here are Invalid
+    states: HashMap<u32, State>,
+    // lines that got invalidated and haven't come back since (for coherence misses)
+    invalidated: HashSet<u32>,
+    stats: CoreStats,
+}
+
+impl Core<'_> {
+    fn state(&self, line_address: u32) -> State {
+        self.states.get(&line_address).copied().unwrap_or(State::Invalid)
+    }
+}
+
+// N cores, each with a private cache (all configured by the same UserInput), kept coherent by
+// snooping on a shared bus
+//
+// the bus is atomic: every request finishes (snoops and all) before the next access starts
+#[derive(Debug)]
+pub struct MultiCore<'a> {
+    context: &'a UserInput,
+    protocol: Protocol,
+    line_size: u32,
+    cores: Vec<Core<'a>>,
+    sharing: Option<SharingDetector<'a>>,
+}
+
+impl<'a> MultiCore<'a> {
+    pub fn new(user_input: &'a UserInput, num_cores: usize, protocol: Protocol) -> Self {
+        assert!(num_cores > 0, "need at least one core");
+
+        let cores = (0..num_cores)
+            .map(|_| Core {
+                cache: Cache::new(user_input),
+                states: HashMap::new(),
+                invalidated: HashSet::new(),
+                stats: CoreStats::default(),
+            })
+            .collect();
+
+        MultiCore { context: user_input, protocol, line_size: user_input.line_size(), cores, sharing: None }
+    }
+
+    // also sort coherence misses into true and false sharing (see sharing.rs)
+    pub fn with_sharing_detector(mut self) -> Self {
+        self.sharing = Some(SharingDetector::new(self.context));
+        self
+    }
+
+    // None unless it was turned on with with_sharing_detector
+    pub fn sharing(&self) -> Option<&SharingDetector<'a>> {
+        self.sharing.as_ref()
+    }
+
+    pub fn num_cores(&self) -> usize {
+        self.cores.len()
+    }
+
+    // the state of the line an address lives in, in one core's cache
+    pub fn state(&self, core: usize, address: u32) -> State {
+        self.cores[core].state(self.line_address(address))
+    }
+
+    pub fn stats(&self) -> Vec<CoreStats> {
+        self.cores.iter().map(|core| core.stats).collect()
+    }
+
+    fn line_address(&self, address: u32) -> u32 {
+        address & !(self.line_size - 1)
+    }
+
+    // one core loads or stores something, returns whether it hit
+    pub fn access(&mut self, core: usize, access: &Access) -> bool {
+        let Access { address, kind, .. } = *access;
+        assert!(core < self.cores.len(), "there is no core {} (there are {})", core, self.cores.len());
+        let line = self.line_address(address);
+        let state = self.cores[core].state(line);
+
+        let hit = state != State::Invalid;
+        let next = match (kind, state) {
+            (AccessKind::Load, State::Invalid) => {
+                self.cores[core].stats.bus_reads += 1;
+                let shared = self.snoop(core, line, |protocol, state| protocol.snoop_read(state));
+                self.protocol.read_miss_state(shared)
+            }
+            (AccessKind::Load, state) => state,
+            (AccessKind::Store, State::Invalid) => {
+                self.cores[core].stats.bus_read_exclusives += 1;
+                self.snoop(core, line, |protocol, state| protocol.snoop_write(state, false));
+                State::Modified
+            }
+            (AccessKind::Store, State::Shared | State::Owned) => {
+                self.cores[core].stats.bus_upgrades += 1;
+                self.snoop(core, line, |protocol, state| protocol.snoop_write(state, true));
+                State::Modified
+            }
+            // E to M is silent, that's the point of E
+            (AccessKind::Store, State::Exclusive | State::Modified) => State::Modified,
+        };
+
+        let me = &mut self.cores[core];
+        me.stats.accesses += 1;
+        if hit {
+            me.stats.hits += 1;
+        } else {
+            me.stats.misses += 1;
+            if me.invalidated.remove(&line) {
+                me.stats.coherence_misses += 1;
+                if let Some(sharing) = &mut self.sharing {
+                    sharing.on_coherence_miss(core, access);
+                }
+            }
+        }
+        if let Some(sharing) = &mut self.sharing {
+            sharing.on_access(core, access);
+        }
+
+        // the cache does the replacement, we just follow along
+        let outcome = me.cache.access(address, kind);
+        debug_assert_eq!(outcome.hit, hit, "the cache and the coherence states disagree");
+        if let Some(evicted) = outcome.evicted {
+            let evicted_state = me.states.remove(&evicted.address).unwrap_or(State::Invalid);
+            if evicted_state.is_dirty() {
+                me.stats.writebacks += 1;
+            }
+        }
+        me.states.insert(line, next);
+
+        hit
+    }
+
+    // tell every other core with the line about a request, returns whether any of them had it
+    fn snoop(&mut self, requester: usize, line: u32, snoop: impl Fn(Protocol, State) -> protocol::Snoop) -> bool {
+        let mut shared = false;
+
+        for (i, core) in self.cores.iter_mut().enumerate() {
+            let state = core.state(line);
+            if i == requester || state == State::Invalid {
+                continue;
+            }
+            shared = true;
+
+            let result = snoop(self.protocol, state);
+            if result.intervention {
+                core.stats.interventions += 1;
+            }
+            if result.writeback {
+                core.stats.writebacks += 1;
+            }
+
+            if result.next == State::Invalid {
+                core.cache.invalidate(line);
+                core.states.remove(&line);
+                core.invalidated.insert(line);
+                core.stats.invalidations += 1;
+                if let Some(sharing) = &mut self.sharing {
+                    sharing.on_invalidate(i, line);
+                }
+            } else {
+                core.states.insert(line, result.next);
+            }
+        }
+
+        shared
+    }
+
+    // run a whole multi core trace, returns the stats for every core
+    //
+    // e.g. multi_core.simulate(trace::CoreColumnSource::open("shared.trace")?)
+    pub fn simulate(&mut self, mut source: impl CoreTraceSource) -> Vec<CoreStats> {
+        while let Some(CoreAccess { core, access }) = source.next_core_access() {
+            self.access(core, &access);
+        }
+
+        self.stats()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::user_input::{CacheType, ReplacementPolicy};
+
+    // 16 lines of 16 bytes, 4 way set associative
+    fn config() -> UserInput {
+        UserInput::with_config(8, 4, CacheType::SetAssociative(2), ReplacementPolicy::LRU)
+    }
+
+    fn source(trace: &str) -> trace::CoreColumnSource<&[u8]> {
+        trace::CoreColumnSource::new(trace.as_bytes(), "test trace")
+    }
+
+    #[test]
+    fn test_ping_pong_writes() {
+        // both cores keep writing the same line
+        let trace = "s 0x00000100 4 0\ns 0x00000104 4 1\ns 0x00000100 4 0\ns 0x00000104 4 1\n";
+        let ui = config();
+        let mut multi_core = MultiCore::new(&ui, 2, Protocol::Mesi);
+        let stats = multi_core.simulate(source(trace));
+
+        assert_eq!(multi_core.state(0, 0x100), State::Invalid);
+        assert_eq!(multi_core.state(1, 0x100), State::Modified);
+        assert_eq!(stats[0], CoreStats { accesses: 2, misses: 2, coherence_misses: 1, invalidations: 2, interventions: 2, bus_read_exclusives: 2, ..Default::default() });
+        assert_eq!(stats[1].coherence_misses, 1);
+        assert_eq!(stats[1].interventions, 1);
+    }
+
+    #[test]
+    fn test_protocols_share_differently() {
+        // core 0 writes, core 1 reads, then core 0 writes again
+        let trace = "s 0x00000100 4 0\nl 0x00000100 4 1\ns 0x00000100 4 0\n";
+
+        let ui = config();
+
+        let msi = MultiCore::new(&ui, 2, Protocol::Msi).simulate(source(trace));
+        assert_eq!(msi[0].writebacks, 1);
+        assert_eq!(msi[0].bus_upgrades, 1);
+        assert_eq!(msi[1].invalidations, 1);
+
+        // MOESI shares the dirty line without writing it back
+        let mut moesi = MultiCore::new(&ui, 2, Protocol::Moesi);
+        let stats = moesi.simulate(source("s 0x00000100 4 0\nl 0x00000100 4 1\n"));
+        assert_eq!(stats[0].writebacks, 0);
+        assert_eq!(moesi.state(0, 0x100), State::Owned);
+        assert_eq!(moesi.state(1, 0x100), State::Shared);
+
+        // MESI reads of unshared lines come in Exclusive, so the write is free
+        let mesi = MultiCore::new(&ui, 2, Protocol::Mesi).simulate(source("l 0x00000200 4 0\ns 0x00000200 4 0\n"));
+        assert_eq!(mesi[0].bus_transactions(), 1);
+    }
+}
@@ -0,0 +1,105 @@
+// which coherence protocol the caches follow
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Protocol {
+    Msi,
+    Mesi,  // MSI + Exclusive: reading a line nobody else has means you can write it without the bus
+    Moesi, // MESI + Owned: a dirty line can be shared without writing it back first
+}
+
+// "msi", "mesi" or "moesi" (any case)
+impl TryFrom<&str> for Protocol {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.to_lowercase().trim() {
+            "msi" => Ok(Protocol::Msi),
+            "mesi" => Ok(Protocol::Mesi),
+            "moesi" => Ok(Protocol::Moesi),
+            _ => Err(format!("the protocol is msi, mesi or moesi. Got {}", value)),
+        }
+    }
+}
+
+// the state of one line in one core's cache
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum State {
+    Modified,  // only copy, dirty
+    Owned,     // dirty, other cores might have it Shared, this core answers for it
+    Exclusive, // only copy, clean
+    Shared,    // clean (or someone else Owns it), other cores might have it too
+    Invalid,   // not in the cache
+}
+
+// what a cache does when it sees another core's request for a line it has
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) struct Snoop {
+    pub(crate) next: State,
+    // it had to answer (it had the line Modified, Owned or Exclusive)
+    pub(crate) intervention: bool,
+    // it had to write the line back to memory
+    pub(crate) writeback: bool,
+}
+
+impl State {
+    // does this cache have to answer requests for the line?
+    fn responds(self) -> bool {
+        matches!(self, State::Modified | State::Owned | State::Exclusive)
+    }
+
+    // does the line have to be written back when it's kicked out?
+    pub(crate) fn is_dirty(self) -> bool {
+        matches!(self, State::Modified | State::Owned)
+    }
+}
+
+impl Protocol {
+    // the state a line gets after a read miss (shared is whether another cache has it)
+    pub(crate) fn read_miss_state(self, shared: bool) -> State {
+        match self {
+            Protocol::Msi => State::Shared,
+            Protocol::Mesi | Protocol::Moesi if shared => State::Shared,
+            Protocol::Mesi | Protocol::Moesi => State::Exclusive,
+        }
+    }
+
+    // another core read a line this cache has (BusRd)
+    pub(crate) fn snoop_read(self, state: State) -> Snoop {
+        let (next, writeback) = match (self, state) {
+            // MOESI keeps the dirty line and answers for it from now on
+            (Protocol::Moesi, State::Modified) => (State::Owned, false),
+            // the others have to clean it up before sharing
+            (_, State::Modified) => (State::Shared, true),
+            (_, State::Owned) => (State::Owned, false),
+            (_, State::Exclusive) => (State::Shared, false),
+            (_, state) => (state, false),
+        };
+
+        Snoop { next, intervention: state.responds(), writeback }
+    }
+
+    // another core is about to write a line this cache has, with BusRdX (it missed) or
+    // BusUpgr (it already had the data)
+    //
+    // either way our copy is gone. A dirty copy goes straight to the writer, so it doesn't have
+    // to be written back
+    pub(crate) fn snoop_write(self, state: State, upgrade: bool) -> Snoop {
+        Snoop { next: State::Invalid, intervention: !upgrade && state.responds(), writeback: false }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_modified_line_gets_read() {
+        let msi = Protocol::Msi.snoop_read(State::Modified);
+        assert_eq!(msi, Snoop { next: State::Shared, intervention: true, writeback: true });
+
+        let moesi = Protocol::Moesi.snoop_read(State::Modified);
+        assert_eq!(moesi, Snoop { next: State::Owned, intervention: true, writeback: false });
+
+        assert_eq!(Protocol::Msi.read_miss_state(false), State::Shared);
+        assert_eq!(Protocol::Mesi.read_miss_state(false), State::Exclusive);
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::cache::{Access, AccessKind};
+use crate::user_input::UserInput;
+
+// sorts coherence misses into true and false sharing (see MultiCore::with_sharing_detector)
+//
+// when a core's copy of a line gets invalidated, we start writing down which bytes of the line
+// other cores write. When that core misses on the line again:
+//  - true sharing: it wants a byte somebody else wrote (the data really had to move)
+//  - false sharing: it only wants bytes nobody else wrote, the line just happened to hold
+//    both (different variables on the same line)
+//
+// only the bytes of the access that missed count (not everything the core does with the line
+// afterwards), and accesses that run past the end of the line are cut off at the end
+#[derive(Debug)]
+pub struct SharingDetector<'a> {
+    context: &'a UserInput,
+    // bytes written by other cores since a core's copy of a line got invalidated, by line and
+    // then core (so a store only looks at its own line)
+    written_since_invalidation: HashMap<u32, HashMap<usize, Vec<bool>>>,
+    lines: HashMap<u32, LineSharing>,
+}
+
+// everything we know about one line
+#[derive(Debug, Default)]
+struct LineSharing {
+    true_sharing_misses: u32,
+    false_sharing_misses: u32,
+    // bytes each core touched (loads and stores), by core
+    touched: HashMap<usize, Vec<bool>>,
+}
+
+// one line in SharingDetector::worst_lines
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct LineReport {
+    pub line_address: u32,
+    pub true_sharing_misses: u32,
+    pub false_sharing_misses: u32,
+    // which bytes (offsets in the line) every core touched, sorted by core
+    pub touched: Vec<(usize, Vec<Range<u32>>)>,
+}
+
+// the runs of true in a byte mask, e.g. [0..4, 8..12]
+fn byte_ranges(bytes: &[bool]) -> Vec<Range<u32>> {
+    let mut ranges: Vec<Range<u32>> = Vec::new();
+
+    for (offset, _) in bytes.iter().enumerate().filter(|(_, touched)| **touched) {
+        let offset = offset as u32;
+        match ranges.last_mut() {
+            Some(range) if range.end == offset => range.end += 1,
+            _ => ranges.push(offset..offset + 1),
+        }
+    }
+
+    ranges
+}
+
+impl<'a> SharingDetector<'a> {
+    pub fn new(user_input: &'a UserInput) -> Self {
+        SharingDetector { context: user_input, written_since_invalidation: HashMap::new(), lines: HashMap::new() }
+    }
+
+    // the line an access is in and which bytes of it the access touches
+    fn line_and_bytes(&self, access: &Access) -> (u32, Range<usize>) {
+        let line_size = self.context.line_size();
+        let offset = self.context.line_offset(access.address);
+
+        let start = offset as usize;
+        let end = (offset as usize + access.size.max(1) as usize).min(line_size as usize);
+        (access.address - offset, start..end)
+    }
+
+    // a core's copy of a line just got invalidated
+    pub(crate) fn on_invalidate(&mut self, core: usize, line_address: u32) {
+        let line_size = self.context.line_size() as usize;
+        self.written_since_invalidation.entry(line_address).or_default().insert(core, vec![false; line_size]);
+    }
+
+    // a core had a coherence miss (call this before on_access for the same access)
+    pub(crate) fn on_coherence_miss(&mut self, core: usize, access: &Access) {
+        let (line_address, bytes) = self.line_and_bytes(access);
+        let Some(cores) = self.written_since_invalidation.get_mut(&line_address) else {
+            return;
+        };
+        let Some(written) = cores.remove(&core) else {
+            return;
+        };
+        if cores.is_empty() {
+            self.written_since_invalidation.remove(&line_address);
+        }
+
+        let line = self.lines.entry(line_address).or_default();
+        if written[bytes].contains(&true) {
+            line.true_sharing_misses += 1;
+        } else {
+            line.false_sharing_misses += 1;
+        }
+    }
+
+    // a core accessed some bytes (after the coherence protocol did its thing)
+    pub(crate) fn on_access(&mut self, core: usize, access: &Access) {
+        let line_size = self.context.line_size() as usize;
+        let (line_address, bytes) = self.line_and_bytes(access);
+
+        let touched = self
+            .lines
+            .entry(line_address)
+            .or_default()
+            .touched
+            .entry(core)
+            .or_insert_with(|| vec![false; line_size]);
+        touched[bytes.clone()].fill(true);
+
+        if access.kind == AccessKind::Store {
+            let Some(cores) = self.written_since_invalidation.get_mut(&line_address) else {
+                return;
+            };
+            for (_, written) in cores.iter_mut().filter(|(other_core, _)| **other_core != core) {
+                written[bytes.clone()].fill(true);
+            }
+        }
+    }
+
+    pub fn true_sharing_misses(&self) -> u32 {
+        self.lines.values().map(|line| line.true_sharing_misses).sum()
+    }
+
+    pub fn false_sharing_misses(&self) -> u32 {
+        self.lines.values().map(|line| line.false_sharing_misses).sum()
+    }
+
+    // the n lines with the most false sharing misses (then the most true sharing misses),
+    // leaving out lines without any sharing misses
+    pub fn worst_lines(&self, n: usize) -> Vec<LineReport> {
+        let mut lines: Vec<_> = self
+            .lines
+            .iter()
+            .filter(|(_, line)| line.true_sharing_misses + line.false_sharing_misses > 0)
+            .collect();
+        lines.sort_by_key(|(line_address, line)| {
+            (std::cmp::Reverse(line.false_sharing_misses), std::cmp::Reverse(line.true_sharing_misses), **line_address)
+        });
+
+        lines
+            .into_iter()
+            .take(n)
+            .map(|(line_address, line)| {
+                let mut touched: Vec<_> = line.touched.iter().map(|(core, bytes)| (*core, byte_ranges(bytes))).collect();
+                touched.sort_by_key(|(core, _)| *core);
+
+                LineReport {
+                    line_address: *line_address,
+                    true_sharing_misses: line.true_sharing_misses,
+                    false_sharing_misses: line.false_sharing_misses,
+                    touched,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coherence::{trace::CoreColumnSource, MultiCore, Protocol};
+    use crate::user_input::{CacheType, ReplacementPolicy};
+
+    use super::*;
+
+    #[test]
+    fn test_false_then_true_sharing() {
+        // 16 byte lines
+        let ui = UserInput::with_config(8, 4, CacheType::SetAssociative(2), ReplacementPolicy::LRU);
+        let mut multi_core = MultiCore::new(&ui, 2, Protocol::Mesi).with_sharing_detector();
+
+        // core 0 and 1 write different words of the line (false sharing when core 0 comes
+        // back), then core 1 reads the word core 0 wrote (true sharing)
+        let trace = "s 0x00000100 4 0\ns 0x00000108 4 1\ns 0x00000100 4 0\nl 0x00000100 4 1\n";
+        multi_core.simulate(CoreColumnSource::new(trace.as_bytes(), "test trace"));
+
+        let sharing = multi_core.sharing().unwrap();
+        assert_eq!((sharing.true_sharing_misses(), sharing.false_sharing_misses()), (1, 1));
+        assert_eq!(
+            sharing.worst_lines(10),
+            vec![LineReport {
+                line_address: 0x100,
+                true_sharing_misses: 1,
+                false_sharing_misses: 1,
+                touched: vec![(0, std::iter::once(0..4).collect()), (1, vec![0..4, 8..12])],
+            }]
+        );
+    }
+}
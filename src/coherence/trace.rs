@@ -0,0 +1,153 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+
+use crate::cache::trace::ReaderSource;
+use crate::cache::{Access, TraceSource};
+
+// one access from one core
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct CoreAccess {
+    pub core: usize,
+    pub access: Access,
+}
+
+// a line of a multi core trace is a normal trace line with the core id at the end,
+// e.g. "l 0x0000AA40 4 1" is core 1 loading 4 bytes
+impl std::str::FromStr for CoreAccess {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let line = line.trim();
+        if line.split_whitespace().count() != 4 {
+            return Err(format!("expected l or s, an address, a size and a core id, got {}", line));
+        }
+
+        let (access, core) = line.rsplit_once(char::is_whitespace).unwrap();
+        let core = core.parse().map_err(|e| format!("bad core id {}: {}", core, e))?;
+        Ok(CoreAccess { core, access: access.parse()? })
+    }
+}
+
+impl std::fmt::Display for CoreAccess {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.access, self.core)
+    }
+}
+
+// anything the multi core simulator can get accesses from (see MultiCore::simulate)
+pub trait CoreTraceSource {
+    // the next access, or None when the trace is over
+    fn next_core_access(&mut self) -> Option<CoreAccess>;
+}
+
+impl<T: CoreTraceSource + ?Sized> CoreTraceSource for Box<T> {
+    fn next_core_access(&mut self) -> Option<CoreAccess> {
+        (**self).next_core_access()
+    }
+}
+
+// lend a source to the simulator, so you can look at it afterwards (e.g. CoreColumnSource::error)
+impl<T: CoreTraceSource + ?Sized> CoreTraceSource for &mut T {
+    fn next_core_access(&mut self) -> Option<CoreAccess> {
+        (**self).next_core_access()
+    }
+}
+
+// one trace file with a core id column (see CoreAccess)
+pub struct CoreColumnSource<R> {
+    reader: ReaderSource<R>,
+    // core ids have to be less than this (see with_cores)
+    cores: usize,
+    // the first core id that was too big, the trace stops there (see error)
+    error: Option<String>,
+}
+
+impl<R: BufRead> CoreColumnSource<R> {
+    pub fn new(reader: R, name: &str) -> Self {
+        CoreColumnSource { reader: ReaderSource::new(reader, name), cores: usize::MAX, error: None }
+    }
+
+    // only core ids from 0 to cores - 1 are allowed, the trace ends at the first one that isn't
+    pub fn with_cores(mut self, cores: usize) -> Self {
+        self.cores = cores;
+        self
+    }
+
+    // what made the trace end early (None if it didn't)
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref().or(self.reader.error())
+    }
+}
+
+impl CoreColumnSource<BufReader<File>> {
+    pub fn open(filepath: &str) -> io::Result<Self> {
+        Ok(CoreColumnSource::new(BufReader::new(File::open(filepath)?), filepath))
+    }
+}
+
+impl<R: BufRead> CoreTraceSource for CoreColumnSource<R> {
+    fn next_core_access(&mut self) -> Option<CoreAccess> {
+        if self.error.is_some() {
+            return None;
+        }
+
+        let access: CoreAccess = self.reader.next_parsed()?;
+        if access.core >= self.cores {
+            self.error = Some(format!("{}: there is no core {} (there are {})", self.reader.location(), access.core, self.cores));
+            return None;
+        }
+        Some(access)
+    }
+}
+
+// a normal trace per core, taking turns one access at a time (core 0, core 1, ... core 0, ...)
+//
+// cores whose trace is over just drop out of the rotation
+pub struct RoundRobinSource<'a> {
+    sources: Vec<Box<dyn TraceSource + 'a>>,
+    finished: Vec<bool>,
+    next_core: usize,
+}
+
+impl<'a> RoundRobinSource<'a> {
+    // core i runs sources[i]
+    pub fn new(sources: Vec<Box<dyn TraceSource + 'a>>) -> Self {
+        let finished = vec![false; sources.len()];
+        RoundRobinSource { sources, finished, next_core: 0 }
+    }
+}
+
+impl CoreTraceSource for RoundRobinSource<'_> {
+    fn next_core_access(&mut self) -> Option<CoreAccess> {
+        while self.finished.contains(&false) {
+            let core = self.next_core;
+            self.next_core = (self.next_core + 1) % self.sources.len();
+
+            if self.finished[core] {
+                continue;
+            }
+
+            match self.sources[core].next_access() {
+                Some(access) => return Some(CoreAccess { core, access }),
+                None => self.finished[core] = true,
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_core_ids_have_to_exist() {
+        let trace = "l 0x00000010 4 0\ns 0x00000010 4 1\n\nl 0x00000020 4 2\nl 0x00000030 4 0\n";
+        let mut source = CoreColumnSource::new(trace.as_bytes(), "test").with_cores(2);
+
+        let cores: Vec<_> = std::iter::from_fn(|| source.next_core_access()).map(|access| access.core).collect();
+        assert_eq!(cores, vec![0, 1]);
+        assert_eq!(source.error(), Some("test line 4: there is no core 2 (there are 2)"));
+    }
+}
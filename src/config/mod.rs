@@ -0,0 +1,413 @@
+pub mod presets;
+
+use std::fs::read_to_string;
+
+use serde::Deserialize;
+
+use crate::cache::replacement::{self, RripPolicy};
+use crate::cache::{hierarchy_amat, prefetch, writeback_stores, Access, AccessKind, Cache, CacheObserver, IndexFunction, Latencies, SimResults, Simulation, TraceSource};
+use crate::user_input::{parse_size, UserInput};
+
+// a config file describes the caches to simulate (from L1 down) and the trace to run, e.g.
+//
+// trace = "trace_files/read01.trace"
+//
+// [[caches]]
+// name = "L1"
+// size = "32K"         # bytes, or with K or M on the end
+// line_size = 64
+// ways = 8             # or "full"
+// policy = "lru"       # lru, fifo, srrip, brrip, drrip, lip, bip or dip (lru by default)
+// rrpv_bits = 3        # only for srrip, brrip and drrip (2 by default)
+// prefetch = "stride"  # next-line, stride or stream (none by default)
+// victim_lines = 4
+// sectors = 2
+// index = "xor-fold"   # see IndexFunction (index_key is the key for randomized)
+// latencies = { hit = 4, miss_penalty = 0, victim_hit = 1 }  # cycles (see Latencies)
+//
+// [[caches]]
+// name = "L2"
+// size = "1536K"
+// line_size = 64
+// ways = 12
+// latencies = { hit = 14 }
+//
+// memory_latency = 200  # cycles, for the last cache's misses (100 by default)
+//
+// instead of caches, `preset = "amd-zen3"` gets the caches of a real processor (see presets.rs)
+//
+// the same thing works as JSON ({"trace": ..., "caches": [{"name": "L1", ...}, ...]})
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawConfig {
+    trace: Option<String>,
+    preset: Option<String>,
+    memory_latency: Option<u32>,
+    #[serde(default)]
+    caches: Vec<RawCache>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawCache {
+    name: Option<String>,
+    size: Value,
+    line_size: Value,
+    ways: Value,
+    policy: Option<String>,
+    rrpv_bits: Option<u8>,
+    prefetch: Option<String>,
+    victim_lines: Option<u32>,
+    sectors: Option<u32>,
+    index: Option<String>,
+    index_key: Option<u64>,
+    latencies: Option<RawLatencies>,
+}
+
+// anything left out is the same as Latencies::default
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawLatencies {
+    hit: Option<u32>,
+    miss_penalty: Option<u32>,
+    victim_hit: Option<u32>,
+}
+
+// sizes can be numbers or strings like "32K", and ways can be "full"
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Value {
+    Number(u32),
+    Text(String),
+}
+
+impl Value {
+    fn size(&self) -> Result<u32, String> {
+        match self {
+            Value::Number(bytes) => Ok(*bytes),
+            Value::Text(text) => parse_size(text),
+        }
+    }
+}
+
+// one cache from a config file, checked and ready to simulate
+#[derive(Debug)]
+pub struct CacheConfig {
+    pub name: String,
+    pub user_input: UserInput,
+    // for the RRIP family (None is the 2 bits from the prompts)
+    pub rrpv_bits: Option<u8>,
+    pub prefetch: Option<String>,
+    pub victim_lines: Option<usize>,
+    pub sectors: Option<u32>,
+    pub index: IndexFunction,
+    // memory_latency is the config's (so it only matters for the last cache) and the TLB ones
+    // are the defaults
+    pub latencies: Latencies,
+}
+
+impl CacheConfig {
+    // a Cache set up like this
+    pub fn cache(&self) -> Cache<'_> {
+        let mut cache = Cache::new(&self.user_input);
+        if let (Some(bits), Some(flavor)) = (self.rrpv_bits, replacement::rrip_flavor(self.user_input.replacement_policy())) {
+            cache.set_replacement_policy(Box::new(RripPolicy::new(flavor, bits)));
+        }
+        if let Some(name) = &self.prefetch {
+            cache.set_prefetcher(prefetch::from_name(name).expect("checked when the config was loaded"));
+        }
+        if let Some(sectors) = self.sectors {
+            cache.set_sectors(sectors);
+        }
+        if let Some(victim_lines) = self.victim_lines {
+            cache.set_victim_cache(victim_lines);
+        }
+        cache.set_index_function(self.index);
+        cache
+    }
+}
+
+// a cache below another one: it watches the cache above it and runs a load for every fetch and
+// a store for every dirty sector of every writeback (the same accesses MissStream writes down),
+// as they happen
+struct Level<'a> {
+    cache: Cache<'a>,
+    simulation: Simulation,
+}
+
+impl<'a> Level<'a> {
+    fn new(cache: Cache<'a>) -> Self {
+        let simulation = Simulation::start(&cache);
+        Level { cache, simulation }
+    }
+
+    fn finish(self) -> SimResults {
+        self.simulation.finish(&self.cache)
+    }
+}
+
+impl CacheObserver for Level<'_> {
+    fn on_fetch(&mut self, address: u32, size: u32) {
+        self.simulation.access(&mut self.cache, Access { kind: AccessKind::Load, address, size });
+    }
+
+    fn on_writeback(&mut self, address: u32, dirty_sectors: u64, sector_size: u32) {
+        for store in writeback_stores(address, dirty_sectors, sector_size) {
+            self.simulation.access(&mut self.cache, store);
+        }
+    }
+}
+
+// run source through caches[0] with the rest of the caches hanging below it, each one watching
+// the one above (see Level), and memory watching the last one. Adds the results L1 first
+//
+// the levels have to be built from the bottom up (every cache borrows the one below it), so
+// this goes down the list and does the work on the way back up
+fn simulate_levels(caches: &[CacheConfig], source: impl TraceSource, memory: Option<&mut dyn CacheObserver>, results: &mut Vec<SimResults>) {
+    let (last, above) = caches.split_last().expect("a config has at least one cache");
+    let mut cache = last.cache();
+    if let Some(memory) = memory {
+        cache.add_observer(memory);
+    }
+
+    if above.is_empty() {
+        results.push(cache.simulate(source));
+        return;
+    }
+    let mut level = Level::new(cache);
+    simulate_levels(above, source, Some(&mut level), results);
+    results.push(level.finish());
+}
+
+// everything in a config file (see RawConfig for what one looks like)
+#[derive(Debug)]
+pub struct Config {
+    pub trace: Option<String>,
+    // L1 first
+    pub caches: Vec<CacheConfig>,
+}
+
+impl Config {
+    // .json files are JSON, anything else is TOML
+    pub fn from_file(filepath: &str) -> Result<Self, String> {
+        let file = read_to_string(filepath).map_err(|e| format!("could not read {}: {}", filepath, e))?;
+        let config = if filepath.ends_with(".json") {
+            Config::from_json(&file)
+        } else {
+            Config::from_toml(&file)
+        };
+        config.map_err(|e| format!("{}: {}", filepath, e))
+    }
+
+    pub fn from_toml(file: &str) -> Result<Self, String> {
+        let raw: RawConfig = toml::from_str(file).map_err(|e| e.to_string())?;
+        Config::check(raw)
+    }
+
+    pub fn from_json(file: &str) -> Result<Self, String> {
+        let raw: RawConfig = serde_json::from_str(file).map_err(|e| e.to_string())?;
+        Config::check(raw)
+    }
+
+    // the caches of a preset (see presets.rs), with no trace
+    pub fn preset(name: &str) -> Result<Self, String> {
+        let preset = presets::find(name).ok_or(format!("there is no preset called {} (there's {})", name, presets::names()))?;
+        Config::from_toml(preset.config)
+    }
+
+    // hierarchical AMAT for results from simulate (see hierarchy_amat)
+    pub fn amat(&self, results: &[SimResults]) -> f64 {
+        let levels: Vec<_> = results.iter().zip(&self.caches).map(|(results, cache)| (results, &cache.latencies)).collect();
+        hierarchy_amat(&levels)
+    }
+
+    // turn what was in the file into caches, or say which key is wrong
+    fn check(raw: RawConfig) -> Result<Self, String> {
+        if let Some(name) = &raw.preset {
+            if !raw.caches.is_empty() {
+                return Err("preset: a config has a preset or caches, not both".into());
+            }
+            let mut preset = Config::preset(name).map_err(|e| format!("preset: {}", e))?;
+            if let Some(memory_latency) = raw.memory_latency {
+                for cache in &mut preset.caches {
+                    cache.latencies.memory_latency = memory_latency;
+                }
+            }
+            return Ok(Config { trace: raw.trace, ..preset });
+        }
+
+        if raw.caches.is_empty() {
+            return Err("caches: there has to be at least one cache".into());
+        }
+
+        let caches = raw
+            .caches
+            .into_iter()
+            .enumerate()
+            .map(|(i, cache)| check_cache(i, cache, raw.memory_latency))
+            .collect::<Result<_, _>>()?;
+        Ok(Config { trace: raw.trace, caches })
+    }
+
+    // run a trace through every cache: L1 sees the trace, and every cache after that sees the
+    // misses and writebacks of the one before it as they happen (see Level)
+    //
+    // returns the results of every cache, L1 first
+    pub fn simulate(&self, source: impl TraceSource) -> Vec<SimResults> {
+        let mut results = Vec::new();
+        simulate_levels(&self.caches, source, None, &mut results);
+        results
+    }
+
+    // simulate, with memory watching the last cache, e.g. a MissTraceWriter to write what it
+    // asked memory for out as a much smaller trace for studying the level below it
+    pub fn simulate_with_memory(&self, source: impl TraceSource, memory: &mut dyn CacheObserver) -> Vec<SimResults> {
+        let mut results = Vec::new();
+        simulate_levels(&self.caches, source, Some(memory), &mut results);
+        results
+    }
+}
+
+fn check_cache(i: usize, raw: RawCache, memory_latency: Option<u32>) -> Result<CacheConfig, String> {
+    let key = |name: &str| format!("caches[{}].{}", i, name);
+
+    let size = raw.size.size().map_err(|e| format!("{}: {}", key("size"), e))?;
+    let line_size = raw.line_size.size().map_err(|e| format!("{}: {}", key("line_size"), e))?;
+    let ways = match &raw.ways {
+        Value::Number(ways) => *ways,
+        Value::Text(text) if text == "full" => size.checked_div(line_size).unwrap_or(0),
+        Value::Text(text) => return Err(format!("{}: {} is not a number or \"full\"", key("ways"), text)),
+    };
+
+    let policy = match raw.policy.as_deref().map(str::to_lowercase).as_deref() {
+        None | Some("lru") | Some("l") => "l".to_string(),
+        Some(policy @ ("fifo" | "srrip" | "brrip" | "drrip" | "lip" | "bip" | "dip")) => policy.to_string(),
+        Some(other) => return Err(format!("{}: {} is not lru, fifo, srrip, brrip, drrip, lip, bip or dip", key("policy"), other)),
+    };
+
+    // blame whichever key makes the combination not work
+    let user_input = UserInput::from_sizes(size, line_size, ways, &policy).map_err(|e| {
+        let culprit = if !line_size.is_power_of_two() {
+            "line_size"
+        } else if line_size > size || !size.is_multiple_of(line_size) {
+            "size"
+        } else {
+            "ways"
+        };
+        format!("{}: {}", key(culprit), e)
+    })?;
+
+    match raw.rrpv_bits {
+        Some(_) if replacement::rrip_flavor(user_input.replacement_policy()).is_none() => {
+            return Err(format!("{}: only srrip, brrip and drrip have RRPVs", key("rrpv_bits")));
+        }
+        Some(bits) if !(1..=8).contains(&bits) => return Err(format!("{}: has to be from 1 to 8, not {}", key("rrpv_bits"), bits)),
+        _ => {}
+    }
+    if let Some(name) = &raw.prefetch {
+        prefetch::from_name(name).map_err(|e| format!("{}: {}", key("prefetch"), e))?;
+    }
+    if raw.victim_lines == Some(0) {
+        return Err(format!("{}: has to be at least 1", key("victim_lines")));
+    }
+    if let Some(sectors) = raw.sectors {
+        if !sectors.is_power_of_two() || sectors > 64 || sectors > line_size {
+            return Err(format!("{}: has to be a power of 2, at most 64 and at most the line size ({}), not {}", key("sectors"), line_size, sectors));
+        }
+    }
+
+    let index = match raw.index.as_deref().map(IndexFunction::try_from).transpose() {
+        Ok(Some(IndexFunction::Randomized { .. })) => IndexFunction::Randomized { key: raw.index_key.unwrap_or(0) },
+        Ok(index) => index.unwrap_or_default(),
+        Err(e) => return Err(format!("{}: {}", key("index"), e)),
+    };
+
+    let defaults = Latencies::default();
+    let latencies = Latencies {
+        hit_latency: raw.latencies.as_ref().and_then(|latencies| latencies.hit).unwrap_or(defaults.hit_latency),
+        miss_penalty: raw.latencies.as_ref().and_then(|latencies| latencies.miss_penalty).unwrap_or(defaults.miss_penalty),
+        victim_hit_latency: raw.latencies.as_ref().and_then(|latencies| latencies.victim_hit).unwrap_or(defaults.victim_hit_latency),
+        memory_latency: memory_latency.unwrap_or(defaults.memory_latency),
+        ..defaults
+    };
+
+    Ok(CacheConfig {
+        name: raw.name.unwrap_or(format!("L{}", i + 1)),
+        user_input,
+        rrpv_bits: raw.rrpv_bits,
+        prefetch: raw.prefetch,
+        victim_lines: raw.victim_lines.map(|lines| lines as usize),
+        sectors: raw.sectors,
+        index,
+        latencies,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::trace::VecSource;
+    use crate::cache::MissStream;
+
+    #[test]
+    fn test_two_levels() {
+        let config = Config::from_toml(
+            "trace = \"some.trace\"\n\n[[caches]]\nsize = 64\nline_size = 16\nways = 1\n\n[[caches]]\nname = \"big\"\nsize = \"1K\"\nline_size = 16\nways = \"full\"\npolicy = \"fifo\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.trace.as_deref(), Some("some.trace"));
+        assert_eq!((config.caches[0].name.as_str(), config.caches[1].name.as_str()), ("L1", "big"));
+        assert_eq!(config.caches[1].user_input.lines_per_set(), 64);
+
+        // 0x000 and 0x040 fight over L1's set 0, but both fit in L2
+        let accesses = [0x000, 0x040, 0x000, 0x040]
+            .iter()
+            .map(|address| Access { kind: AccessKind::Store, address: *address, size: 4 })
+            .collect::<Vec<_>>();
+        let mut memory = MissStream::new();
+        let results = config.simulate_with_memory(VecSource::from(accesses), &mut memory);
+
+        assert_eq!((results[0].hits, results[0].misses, results[0].writebacks), (0, 4, 3));
+        // 4 loads from L1's misses and 3 stores from its writebacks
+        assert_eq!((results[1].accesses, results[1].hits), (7, 5));
+        assert_eq!((results[1].loads, results[1].load_misses), (4, 2));
+        // and memory only gets asked for the 2 lines once
+        let load = |address| Access { kind: AccessKind::Load, address, size: 16 };
+        assert_eq!(memory.accesses, vec![load(0x000), load(0x040)]);
+        // 1 + 1 * (1 + 2/4 * 100) with the default latencies, the writebacks' misses don't count
+        assert!((config.amat(&results) - 52.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_errors_point_at_the_key() {
+        let json = |cache: &str| Config::from_json(&format!("{{\"caches\": [{{\"size\": 1024, \"line_size\": 64, \"ways\": 2}}, {}]}}", cache));
+
+        assert_eq!(
+            json("{\"size\": 1024, \"line_size\": 64, \"ways\": 3}").unwrap_err(),
+            "caches[1].ways: 16 lines don't split into sets of 3 (the cache size has to be a multiple of ways * line size)"
+        );
+        assert!(json("{\"size\": \"1Q\", \"line_size\": 64, \"ways\": 2}").unwrap_err().starts_with("caches[1].size: "));
+        assert!(json("{\"size\": 1024, \"line_size\": 64, \"ways\": 2, \"index\": \"md5\"}").unwrap_err().starts_with("caches[1].index: "));
+        assert!(json("{\"size\": 1024, \"line_size\": 64, \"wayz\": 2}").unwrap_err().contains("unknown field `wayz`"));
+        assert!(json("{\"size\": 1024, \"line_size\": 64, \"ways\": 2, \"rrpv_bits\": 3}").unwrap_err().starts_with("caches[1].rrpv_bits: "));
+        assert!(json("{\"size\": 1024, \"line_size\": 64, \"ways\": 2, \"policy\": \"srrip\", \"rrpv_bits\": 3}").is_ok());
+        assert!(json("{\"size\": 1024, \"line_size\": 64, \"ways\": 2, \"latencies\": {\"hitt\": 2}}").unwrap_err().contains("unknown field `hitt`"));
+        assert_eq!(Config::from_toml("trace = \"a\"").unwrap_err(), "caches: there has to be at least one cache");
+    }
+
+    #[test]
+    fn test_presets() {
+        for preset in presets::PRESETS {
+            let config = Config::preset(preset.name).unwrap_or_else(|e| panic!("{}: {}", preset.name, e));
+            assert!(config.trace.is_none());
+        }
+
+        let config = Config::from_toml("trace = \"t\"\npreset = \"intel-alder-lake\"").unwrap();
+        assert_eq!((config.trace.as_deref(), config.caches.len()), (Some("t"), 3));
+        assert_eq!(config.caches[1].user_input.num_sets(), 2048);
+        assert_eq!((config.caches[0].latencies.hit_latency, config.caches[2].latencies.memory_latency), (5, 300));
+        let config = Config::from_toml("memory_latency = 80\npreset = \"amd-zen3\"").unwrap();
+        assert!(config.caches.iter().all(|cache| cache.latencies.memory_latency == 80));
+        assert!(Config::from_toml("preset = \"pentium-4\"").unwrap_err().starts_with("preset: there is no preset called pentium-4"));
+    }
+}
@@ -0,0 +1,183 @@
+// cache hierarchies of real processors, so nobody has to type them in again
+//
+// the numbers are per core (shared L3s are the whole L3, as if one core had it to itself) and
+// only the data side is here, since traces don't have instruction fetches. Replacement and
+// prefetching are the closest things this simulator has, not what the hardware really does,
+// and the latencies (in core cycles) are rough published load-to-use numbers.
+#[derive(Debug)]
+pub struct Preset {
+    pub name: &'static str,
+    pub description: &'static str,
+    // a config file (see config/mod.rs) without a trace
+    pub config: &'static str,
+}
+
+pub const PRESETS: &[Preset] = &[
+    Preset {
+        name: "intel-skylake",
+        description: "Intel Skylake client core: 32KB 8 way L1D, 256KB 4 way L2, 8MB 16 way L3",
+        config: r#"
+memory_latency = 250
+
+[[caches]]
+name = "L1D"
+size = "32K"
+line_size = 64
+ways = 8
+prefetch = "next-line"
+latencies = { hit = 4 }
+
+[[caches]]
+name = "L2"
+size = "256K"
+line_size = 64
+ways = 4
+prefetch = "stream"
+latencies = { hit = 12 }
+
+[[caches]]
+name = "L3"
+size = "8M"
+line_size = 64
+ways = 16
+policy = "drrip"
+latencies = { hit = 42 }
+"#,
+    },
+    Preset {
+        name: "intel-alder-lake",
+        description: "Intel Alder Lake performance core: 48KB 12 way L1D, 1.25MB 10 way L2, 30MB 12 way L3",
+        config: r#"
+memory_latency = 300
+
+[[caches]]
+name = "L1D"
+size = "48K"
+line_size = 64
+ways = 12
+prefetch = "next-line"
+latencies = { hit = 5 }
+
+[[caches]]
+name = "L2"
+size = "1280K"
+line_size = 64
+ways = 10
+prefetch = "stream"
+latencies = { hit = 15 }
+
+[[caches]]
+name = "L3"
+size = "30M"
+line_size = 64
+ways = 12
+policy = "drrip"
+latencies = { hit = 65 }
+"#,
+    },
+    Preset {
+        name: "amd-zen3",
+        description: "AMD Zen 3 core: 32KB 8 way L1D, 512KB 8 way L2, 32MB 16 way L3 (one CCD)",
+        config: r#"
+memory_latency = 250
+
+[[caches]]
+name = "L1D"
+size = "32K"
+line_size = 64
+ways = 8
+prefetch = "stride"
+latencies = { hit = 4 }
+
+[[caches]]
+name = "L2"
+size = "512K"
+line_size = 64
+ways = 8
+prefetch = "stream"
+latencies = { hit = 12 }
+
+[[caches]]
+name = "L3"
+size = "32M"
+line_size = 64
+ways = 16
+latencies = { hit = 46 }
+"#,
+    },
+    Preset {
+        name: "arm-cortex-a76",
+        description: "Arm Cortex-A76: 64KB 4 way L1D, 512KB 8 way L2, 2MB 16 way L3",
+        config: r#"
+memory_latency = 200
+
+[[caches]]
+name = "L1D"
+size = "64K"
+line_size = 64
+ways = 4
+prefetch = "stride"
+latencies = { hit = 4 }
+
+[[caches]]
+name = "L2"
+size = "512K"
+line_size = 64
+ways = 8
+latencies = { hit = 9 }
+
+[[caches]]
+name = "L3"
+size = "2M"
+line_size = 64
+ways = 16
+latencies = { hit = 31 }
+"#,
+    },
+    Preset {
+        name: "arm-cortex-a53",
+        description: "Arm Cortex-A53: 32KB 4 way L1D, 512KB 16 way L2",
+        config: r#"
+memory_latency = 150
+
+[[caches]]
+name = "L1D"
+size = "32K"
+line_size = 64
+ways = 4
+latencies = { hit = 3 }
+
+[[caches]]
+name = "L2"
+size = "512K"
+line_size = 64
+ways = 16
+latencies = { hit = 15 }
+"#,
+    },
+    Preset {
+        name: "riscv-embedded",
+        description: "small embedded RISC-V core: 16KB 4 way L1D with 32 byte lines, no L2",
+        config: r#"
+memory_latency = 30
+
+[[caches]]
+name = "L1D"
+size = "16K"
+line_size = 32
+ways = 4
+policy = "fifo"
+latencies = { hit = 1 }
+"#,
+    },
+];
+
+// the preset with this name
+pub fn find(name: &str) -> Option<&'static Preset> {
+    PRESETS.iter().find(|preset| preset.name == name.trim())
+}
+
+// for error messages
+pub(crate) fn names() -> String {
+    PRESETS.iter().map(|preset| preset.name).collect::<Vec<_>>().join(", ")
+}
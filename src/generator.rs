@@ -0,0 +1,384 @@
+use crate::cache::trace::GeneratorSource;
+use crate::cache::{Access, AccessKind, TraceSource};
+use crate::random::Rng;
+
+// the kinds of synthetic traces (see Generator)
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Pattern {
+    // one access after another through the footprint, over and over
+    Sequential,
+    // every stride bytes through the footprint, wrapping around
+    Strided { stride: u32 },
+    // anywhere in the footprint, all equally likely
+    Uniform,
+    // the footprint is a list of access sized items, and the k-th item gets picked with a
+    // probability that goes like 1/k^exponent (the hottest items are at the start)
+    Zipf { exponent: f64 },
+    // a linked list of node_size byte nodes in a random order, where every load is the next
+    // pointer of the last one (so no two nodes next to each other in memory are next to each
+    // other in the list)
+    PointerChase { node_size: u32 },
+    // C = A * B for n by n matrices (the textbook i, j, k loops)
+    MatMul { n: u32 },
+    // C = A * B for n by n matrices, in block by block tiles so every tile stays in the cache
+    // while it's being used
+    BlockedMatMul { n: u32, block: u32 },
+}
+
+impl Pattern {
+    // sequential, strided, uniform, zipf, pointer-chase, matmul or blocked-matmul, with the
+    // numbers they need from get (which gets the name of the number and returns None if it
+    // wasn't given)
+    pub fn from_name(name: &str, get: impl Fn(&str) -> Option<f64>) -> Result<Self, String> {
+        let whole = |number: &str, default: u32| get(number).map_or(default, |value| value as u32);
+
+        match name.to_lowercase().trim() {
+            "sequential" => Ok(Pattern::Sequential),
+            "strided" => Ok(Pattern::Strided { stride: whole("stride", 64) }),
+            "uniform" => Ok(Pattern::Uniform),
+            "zipf" => Ok(Pattern::Zipf { exponent: get("exponent").unwrap_or(1.0) }),
+            "pointer-chase" => Ok(Pattern::PointerChase { node_size: whole("node-size", 64) }),
+            "matmul" => Ok(Pattern::MatMul { n: whole("n", 64) }),
+            "blocked-matmul" => Ok(Pattern::BlockedMatMul { n: whole("n", 64), block: whole("block", 16) }),
+            other => Err(format!(
+                "{} is not sequential, strided, uniform, zipf, pointer-chase, matmul or blocked-matmul",
+                other
+            )),
+        }
+    }
+}
+
+// makes up traces in the same format as trace_files/, e.g. 100000 accesses to a 1MB footprint
+// where a few lines are really hot:
+// Generator::new(Pattern::Zipf { exponent: 1.0 }).with_accesses(100_000).with_footprint(1 << 20).generate()
+//
+// the same settings and seed always give the same trace. The matrix multiplies are as long as
+// their loops are, so they ignore accesses (and footprint, they use 3 * n * n elements)
+#[derive(Debug, Clone)]
+pub struct Generator {
+    pattern: Pattern,
+    accesses: usize,
+    // every address is in base..base + footprint
+    base: u32,
+    footprint: u32,
+    // bytes per access (and per matrix element)
+    access_size: u32,
+    // how many of the accesses are stores (not for pointer chasing and the matrix multiplies,
+    // which store what the loops store)
+    store_fraction: f64,
+    seed: u64,
+}
+
+impl Generator {
+    // 10000 4 byte accesses to a 64KB footprint starting at 0, no stores, seed 0
+    pub fn new(pattern: Pattern) -> Self {
+        Generator { pattern, accesses: 10_000, base: 0, footprint: 1 << 16, access_size: 4, store_fraction: 0.0, seed: 0 }
+    }
+
+    pub fn with_accesses(mut self, accesses: usize) -> Self {
+        self.accesses = accesses;
+        self
+    }
+
+    pub fn with_base(mut self, base: u32) -> Self {
+        self.base = base;
+        self
+    }
+
+    pub fn with_footprint(mut self, footprint: u32) -> Self {
+        self.footprint = footprint;
+        self
+    }
+
+    pub fn with_access_size(mut self, access_size: u32) -> Self {
+        self.access_size = access_size;
+        self
+    }
+
+    pub fn with_store_fraction(mut self, store_fraction: f64) -> Self {
+        self.store_fraction = store_fraction;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    // make sure the trace fits in 32 bit addresses and the pattern makes sense
+    fn check(&self) -> Result<(), String> {
+        if self.access_size == 0 {
+            return Err("accesses have to be at least 1 byte".into());
+        }
+        if !(0.0..=1.0).contains(&self.store_fraction) {
+            return Err(format!("the store fraction has to be from 0 to 1, not {}", self.store_fraction));
+        }
+
+        let footprint = match self.pattern {
+            Pattern::MatMul { n } | Pattern::BlockedMatMul { n, .. } => 3 * n as u64 * n as u64 * self.access_size as u64,
+            _ => self.footprint as u64,
+        };
+        if self.base as u64 + footprint > 1 << 32 {
+            return Err(format!("{} bytes starting at 0x{:08x} don't fit in 32 bit addresses", footprint, self.base));
+        }
+
+        match self.pattern {
+            _ if footprint < self.access_size as u64 => Err("the footprint is smaller than one access".into()),
+            Pattern::Strided { stride: 0 } => Err("the stride has to be at least 1 byte".into()),
+            Pattern::Zipf { exponent } if exponent < 0.0 => Err(format!("the zipf exponent can't be negative, got {}", exponent)),
+            Pattern::PointerChase { node_size } if node_size == 0 || node_size > self.footprint => {
+                Err(format!("{} byte nodes don't fit in a {} byte footprint", node_size, self.footprint))
+            }
+            Pattern::MatMul { n: 0 } | Pattern::BlockedMatMul { n: 0, .. } => Err("the matrices need at least 1 row".into()),
+            Pattern::BlockedMatMul { block: 0, .. } => Err("blocks need at least 1 row".into()),
+            _ => Ok(()),
+        }
+    }
+
+    // the trace (made up one access at a time as it's read, so a billion accesses don't have
+    // to fit in memory), or what's wrong with the settings
+    pub fn generate(&self) -> Result<impl TraceSource, String> {
+        self.check()?;
+        let generator = self.clone();
+        let rng = Rng::new(self.seed);
+
+        let mut trace: Box<dyn Iterator<Item = Access>> = match self.pattern {
+            Pattern::Sequential => generator.walk(rng, self.access_size),
+            Pattern::Strided { stride } => generator.walk(rng, stride),
+            Pattern::Uniform => generator.uniform(rng),
+            Pattern::Zipf { exponent } => generator.zipf(rng, exponent),
+            Pattern::PointerChase { node_size } => generator.pointer_chase(rng, node_size),
+            Pattern::MatMul { n } => generator.matmul(n, n),
+            Pattern::BlockedMatMul { n, block } => generator.matmul(n, block.min(n)),
+        };
+        Ok(GeneratorSource::new(move || trace.next()))
+    }
+
+    // an access at base + offset, a store store_fraction of the time
+    fn access(&self, rng: &mut Rng, offset: u32) -> Access {
+        let kind = if self.store_fraction > 0.0 && rng.chance(self.store_fraction) {
+            AccessKind::Store
+        } else {
+            AccessKind::Load
+        };
+        Access { kind, address: self.base + offset, size: self.access_size }
+    }
+
+    fn walk(self, mut rng: Rng, stride: u32) -> Box<dyn Iterator<Item = Access>> {
+        Box::new((0..self.accesses as u64).map(move |i| {
+            let offset = (i * stride as u64 % self.footprint as u64) as u32;
+            self.access(&mut rng, offset)
+        }))
+    }
+
+    fn uniform(self, mut rng: Rng) -> Box<dyn Iterator<Item = Access>> {
+        let items = (self.footprint / self.access_size) as u64;
+        Box::new((0..self.accesses).map(move |_| {
+            let offset = rng.below(items) as u32 * self.access_size;
+            self.access(&mut rng, offset)
+        }))
+    }
+
+    fn zipf(self, mut rng: Rng, exponent: f64) -> Box<dyn Iterator<Item = Access>> {
+        // item 0 is the hottest (see ZipfSampler, which counts from 1)
+        let sampler = ZipfSampler::new((self.footprint / self.access_size) as u64, exponent);
+        Box::new((0..self.accesses).map(move |_| {
+            let item = sampler.sample(&mut rng) - 1;
+            self.access(&mut rng, item as u32 * self.access_size)
+        }))
+    }
+
+    fn pointer_chase(self, mut rng: Rng, node_size: u32) -> Box<dyn Iterator<Item = Access>> {
+        // one big random cycle through every node (Sattolo's algorithm)
+        let nodes = (self.footprint / node_size) as usize;
+        let mut next: Vec<usize> = (0..nodes).collect();
+        for i in (1..nodes).rev() {
+            let j = rng.below(i as u64) as usize;
+            next.swap(i, j);
+        }
+
+        let mut node = 0;
+        Box::new((0..self.accesses).map(move |_| {
+            node = next[node];
+            Access { kind: AccessKind::Load, address: self.base + node as u32 * node_size, size: self.access_size }
+        }))
+    }
+
+    // C = A * B with A, B and C one after another starting at base, all row major
+    //
+    // block == n is the textbook loops. Otherwise every block by block tile of C gets the
+    // products of tiles of A and B added to it, one pair of tiles at a time
+    fn matmul(self, n: u32, block: u32) -> Box<dyn Iterator<Item = Access>> {
+        let (base, access_size) = (self.base, self.access_size);
+        let element = move |matrix: u32, row: u32, column: u32| base + ((matrix * n + row) * n + column) * access_size;
+        let (a, b, c) = (0, 1, 2);
+        let load = move |address| Access { kind: AccessKind::Load, address, size: access_size };
+        let store = move |address| Access { kind: AccessKind::Store, address, size: access_size };
+
+        // the loops over tiles, then the loops inside a tile
+        let tiles = (0..n).step_by(block as usize);
+        let tile = move |start: u32| start..(start + block).min(n);
+        let tile_triples = tiles.clone().flat_map(move |ii| {
+            let tiles = tiles.clone();
+            tiles.clone().flat_map(move |jj| tiles.clone().map(move |kk| (ii, jj, kk)))
+        });
+        let elements = tile_triples.flat_map(move |(ii, jj, kk)| tile(ii).flat_map(move |i| tile(jj).map(move |j| (i, j, kk))));
+
+        Box::new(elements.flat_map(move |(i, j, kk)| {
+            // the textbook loops keep the sum in a register, tiles have to add to what's
+            // already in C
+            let sum = (block != n).then(|| load(element(c, i, j)));
+            let products = tile(kk).flat_map(move |k| [load(element(a, i, k)), load(element(b, k, j))]);
+            sum.into_iter().chain(products).chain(std::iter::once(store(element(c, i, j))))
+        }))
+    }
+}
+
+// picks items 1..=n, item k with a chance proportional to 1 / k^exponent, without a table of
+// all n chances (rejection-inversion, Hörmann and Derflinger 1996, the same way Apache Commons
+// does it). It samples a smooth curve that's a little bigger than the real chances and throws
+// away the few picks that land above them, so footprints of gigabytes cost nothing up front
+#[derive(Debug, Clone)]
+struct ZipfSampler {
+    items: f64,
+    exponent: f64,
+    h_integral_x1: f64,
+    h_integral_items: f64,
+    // picks this close to the integer they round to always get kept
+    s: f64,
+}
+
+impl ZipfSampler {
+    fn new(items: u64, exponent: f64) -> Self {
+        let mut sampler = ZipfSampler { items: items as f64, exponent, h_integral_x1: 0.0, h_integral_items: 0.0, s: 0.0 };
+        sampler.h_integral_x1 = sampler.h_integral(1.5) - 1.0;
+        sampler.h_integral_items = sampler.h_integral(sampler.items + 0.5);
+        sampler.s = 2.0 - sampler.h_integral_inverse(sampler.h_integral(2.5) - sampler.h(2.0));
+        sampler
+    }
+
+    fn sample(&self, rng: &mut Rng) -> u64 {
+        loop {
+            let u = self.h_integral_items + rng.next_f64() * (self.h_integral_x1 - self.h_integral_items);
+            let x = self.h_integral_inverse(u);
+            let k = (x + 0.5).clamp(1.0, self.items).floor();
+            if k - x <= self.s || u >= self.h_integral(k + 0.5) - self.h(k) {
+                return k as u64;
+            }
+        }
+    }
+
+    // the curve, 1 / x^exponent
+    fn h(&self, x: f64) -> f64 {
+        (-self.exponent * x.ln()).exp()
+    }
+
+    // its integral, (x^(1 - exponent) - 1) / (1 - exponent), which is ln x for exponent 1
+    fn h_integral(&self, x: f64) -> f64 {
+        let log_x = x.ln();
+        expm1_over_x((1.0 - self.exponent) * log_x) * log_x
+    }
+
+    fn h_integral_inverse(&self, x: f64) -> f64 {
+        // rounding can push t a little under -1, where the log blows up
+        let t = (x * (1.0 - self.exponent)).max(-1.0);
+        (ln1p_over_x(t) * x).exp()
+    }
+}
+
+// (e^x - 1) / x and ln(1 + x) / x, without dividing by 0 when x is (nearly) 0
+fn expm1_over_x(x: f64) -> f64 {
+    if x.abs() > 1e-8 {
+        x.exp_m1() / x
+    } else {
+        1.0 + x / 2.0 * (1.0 + x / 3.0 * (1.0 + x / 4.0))
+    }
+}
+
+fn ln1p_over_x(x: f64) -> f64 {
+    if x.abs() > 1e-8 {
+        x.ln_1p() / x
+    } else {
+        1.0 - x * (0.5 - x * (1.0 / 3.0 - 0.25 * x))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn trace(generator: &Generator) -> Vec<Access> {
+        let mut source = generator.generate().unwrap();
+        std::iter::from_fn(|| source.next_access()).collect()
+    }
+
+    #[test]
+    fn test_patterns() {
+        let addresses = |generator: Generator| trace(&generator).iter().map(|access| access.address).collect::<Vec<_>>();
+
+        let strided = Generator::new(Pattern::Strided { stride: 64 }).with_accesses(5).with_footprint(256).with_base(0x1000);
+        assert_eq!(addresses(strided), vec![0x1000, 0x1040, 0x1080, 0x10c0, 0x1000]);
+
+        // a pointer chase visits every node once before coming back around
+        let chase = addresses(Generator::new(Pattern::PointerChase { node_size: 64 }).with_accesses(16).with_footprint(1024).with_seed(3));
+        assert_eq!(chase.iter().collect::<HashSet<_>>().len(), 16);
+        assert!(chase.windows(2).any(|pair| pair[1] != pair[0] + 64));
+
+        // the same seed gives the same trace, and item 0 is the hottest
+        let zipf = || Generator::new(Pattern::Zipf { exponent: 1.0 }).with_accesses(1000).with_store_fraction(0.5).with_seed(9);
+        let zipf_trace = trace(&zipf());
+        assert_eq!(zipf_trace, trace(&zipf()));
+        let zeros = zipf_trace.iter().filter(|access| access.address == 0).count();
+        assert!(zeros > zipf_trace.iter().filter(|access| access.address == 4).count());
+        assert!(zipf_trace.iter().any(|access| access.kind == AccessKind::Store));
+
+        assert!(Generator::new(Pattern::Uniform).with_footprint(u32::MAX).with_base(16).generate().is_err());
+    }
+
+    #[test]
+    fn test_zipf_chances() {
+        // with 16 items and exponent 1, item k comes up 1 / (k * (1 + 1/2 + ... + 1/16)) of the time
+        let harmonic: f64 = (1..=16).map(|k| 1.0 / k as f64).sum();
+        let mut rng = Rng::new(5);
+        let sampler = ZipfSampler::new(16, 1.0);
+        let mut counts = [0u32; 17];
+        for _ in 0..100_000 {
+            counts[sampler.sample(&mut rng) as usize] += 1;
+        }
+        for k in [1, 2, 16] {
+            let expected = 100_000.0 / (k as f64 * harmonic);
+            assert!((counts[k] as f64 - expected).abs() < expected * 0.05, "item {}: {} vs {}", k, counts[k], expected);
+        }
+        // exponent 0 is uniform
+        let uniform = ZipfSampler::new(4, 0.0);
+        let mut counts = [0u32; 5];
+        for _ in 0..40_000 {
+            counts[uniform.sample(&mut rng) as usize] += 1;
+        }
+        assert!(counts[1..].iter().all(|count| (*count as i32 - 10_000).abs() < 500), "{:?}", counts);
+
+        // and a 4GB footprint doesn't need a table of a billion chances
+        let huge = Generator::new(Pattern::Zipf { exponent: 1.2 }).with_footprint(u32::MAX).with_accesses(1000);
+        assert!(trace(&huge).iter().all(|access| access.address <= u32::MAX - 4));
+    }
+
+    #[test]
+    fn test_blocking_helps() {
+        use crate::cache::{trace::VecSource, Cache};
+        use crate::user_input::{CacheType, ReplacementPolicy, UserInput};
+
+        // 32x32 matrices of 8 byte elements (8KB each) through a 2KB cache
+        let ui = UserInput::with_config(11, 5, CacheType::SetAssociative(2), ReplacementPolicy::LRU);
+        let misses = |pattern| {
+            let trace = trace(&Generator::new(pattern).with_access_size(8));
+            let naive = trace.len();
+            (Cache::new(&ui).simulate(VecSource::from(trace)).misses, naive)
+        };
+
+        let (naive_misses, naive_accesses) = misses(Pattern::MatMul { n: 32 });
+        let (blocked_misses, _) = misses(Pattern::BlockedMatMul { n: 32, block: 8 });
+        assert_eq!(naive_accesses, 32 * 32 * (2 * 32 + 1));
+        assert!(blocked_misses < naive_misses, "blocked {} vs naive {}", blocked_misses, naive_misses);
+    }
+}
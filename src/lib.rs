@@ -4,3 +4,21 @@ pub mod user_input;
 // cache simulator
 pub mod cache;
 
+// virtual memory (TLBs and page tables) in front of the cache
+pub mod vm;
+
+// config files (TOML or JSON) describing caches from L1 down
+pub mod config;
+
+// multi core caches kept coherent with MSI, MESI or MOESI
+pub mod coherence;
+
+
+// synthetic traces (streams, strides, random, zipf, pointer chasing, matrix multiplies)
+pub mod generator;
+
+// looking at traces before simulating them
+pub mod trace_tools;
+
+// seeded random numbers (so random things can be reproduced)
+pub mod random;
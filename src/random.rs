@@ -0,0 +1,79 @@
+// a tiny random number generator (xorshift64*)
+//
+// nowhere near good enough for anything serious, but it's fast, doesn't need another
+// dependency, and the same seed always gives the same numbers, so simulations that use it
+// can be reproduced
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // xorshift gets stuck on 0, so scramble the seed first
+        let z = splitmix64(seed);
+        Rng { state: if z == 0 { 1 } else { z } }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    // a number from 0 up to (but not including) n
+    pub fn below(&mut self, n: u64) -> u64 {
+        assert!(n > 0, "can't pick a number below 0");
+        // the bias from % is way too small to matter here
+        self.next_u64() % n
+    }
+
+    // a number from 0 up to (but not including) 1
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // true with probability p
+    pub fn chance(&mut self, p: f64) -> bool {
+        self.next_f64() < p
+    }
+}
+
+// scramble a number so that numbers next to each other come out nowhere near each other (every
+// output comes from exactly one input)
+pub(crate) fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_numbers() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        let mut c = Rng::new(43);
+
+        let a: Vec<u64> = (0..10).map(|_| a.next_u64()).collect();
+        let b: Vec<u64> = (0..10).map(|_| b.next_u64()).collect();
+        let c: Vec<u64> = (0..10).map(|_| c.next_u64()).collect();
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn test_below_stays_below() {
+        let mut rng = Rng::new(0);
+        assert!((0..1000).all(|_| rng.below(7) < 7));
+        assert!((0..1000).all(|_| (0.0..1.0).contains(&rng.next_f64())));
+    }
+}
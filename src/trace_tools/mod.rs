@@ -0,0 +1,5 @@
+mod stats;
+mod transform;
+
+pub use stats::TraceStats;
+pub use transform::{filter, interleave, interleave_by_time, sample_every, sample_random, slice, TimedAccess, TimestampSource};
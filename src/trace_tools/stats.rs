@@ -0,0 +1,127 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::cache::{Access, AccessKind, TraceSource};
+
+// what's in a trace, without simulating anything
+//
+// e.g. TraceStats::of(trace::open("trace_files/read01.trace")?, 64, 1 << 20)
+#[derive(Debug, Clone)]
+pub struct TraceStats {
+    line_size: u32,
+    region_size: u32,
+    pub accesses: u64,
+    pub loads: u64,
+    pub stores: u64,
+    // the lowest and highest address (None for an empty trace)
+    pub address_range: Option<(u32, u32)>,
+    addresses: HashSet<u32>,
+    lines: HashSet<u32>,
+    // accesses per region_size byte region (by the address the region starts at)
+    pub regions: BTreeMap<u32, u64>,
+    // accesses per access size
+    pub sizes: BTreeMap<u32, u64>,
+    // how far every access is from the one before it, in bytes
+    strides: HashMap<i64, u64>,
+    last_address: Option<u32>,
+}
+
+impl TraceStats {
+    // lines are line_size bytes (for unique_lines and footprint) and the address histogram has
+    // region_size byte buckets, both powers of 2
+    pub fn new(line_size: u32, region_size: u32) -> Self {
+        assert!(line_size.is_power_of_two() && region_size.is_power_of_two(), "line and region sizes have to be powers of 2");
+
+        TraceStats {
+            line_size,
+            region_size,
+            accesses: 0,
+            loads: 0,
+            stores: 0,
+            address_range: None,
+            addresses: HashSet::new(),
+            lines: HashSet::new(),
+            regions: BTreeMap::new(),
+            sizes: BTreeMap::new(),
+            strides: HashMap::new(),
+            last_address: None,
+        }
+    }
+
+    // the stats of everything in a trace source
+    pub fn of(mut source: impl TraceSource, line_size: u32, region_size: u32) -> Self {
+        let mut stats = TraceStats::new(line_size, region_size);
+        while let Some(access) = source.next_access() {
+            stats.record(&access);
+        }
+        stats
+    }
+
+    pub fn record(&mut self, access: &Access) {
+        let address = access.address;
+        self.accesses += 1;
+        match access.kind {
+            AccessKind::Load => self.loads += 1,
+            AccessKind::Store => self.stores += 1,
+        }
+
+        self.address_range = match self.address_range {
+            None => Some((address, address)),
+            Some((low, high)) => Some((low.min(address), high.max(address))),
+        };
+        self.addresses.insert(address);
+        self.lines.insert(address & !(self.line_size - 1));
+        *self.regions.entry(address & !(self.region_size - 1)).or_default() += 1;
+        *self.sizes.entry(access.size).or_default() += 1;
+
+        if let Some(last_address) = self.last_address {
+            *self.strides.entry(address as i64 - last_address as i64).or_default() += 1;
+        }
+        self.last_address = Some(address);
+    }
+
+    pub fn unique_addresses(&self) -> usize {
+        self.addresses.len()
+    }
+
+    pub fn unique_lines(&self) -> usize {
+        self.lines.len()
+    }
+
+    // bytes of memory the trace touches, in whole lines
+    pub fn footprint(&self) -> u64 {
+        self.lines.len() as u64 * self.line_size as u64
+    }
+
+    // stores / loads (infinite with only stores)
+    pub fn store_load_ratio(&self) -> f64 {
+        self.stores as f64 / self.loads as f64
+    }
+
+    // the n most common strides and how often they happen, most common first (ties go to the
+    // shorter stride)
+    pub fn top_strides(&self, n: usize) -> Vec<(i64, u64)> {
+        let mut strides: Vec<_> = self.strides.iter().map(|(stride, count)| (*stride, *count)).collect();
+        strides.sort_by_key(|(stride, count)| (std::cmp::Reverse(*count), stride.abs(), *stride));
+        strides.truncate(n);
+        strides
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::trace::ReaderSource;
+
+    #[test]
+    fn test_stats() {
+        let trace = "l 0x00000000 4\nl 0x00000004 4\ns 0x00000008 8\nl 0x0000000c 4\ns 0x00100000 4\nl 0x00000000 4\n";
+        let stats = TraceStats::of(ReaderSource::new(trace.as_bytes(), "test"), 64, 1 << 20);
+
+        assert_eq!((stats.accesses, stats.loads, stats.stores), (6, 4, 2));
+        assert_eq!(stats.address_range, Some((0, 0x100000)));
+        assert_eq!((stats.unique_addresses(), stats.unique_lines(), stats.footprint()), (5, 2, 128));
+        assert_eq!(stats.regions, BTreeMap::from([(0, 5), (0x100000, 1)]));
+        assert_eq!(stats.sizes, BTreeMap::from([(4, 5), (8, 1)]));
+        assert_eq!(stats.top_strides(2), vec![(4, 3), (0xffff4, 1)]);
+    }
+}
@@ -0,0 +1,224 @@
+use std::io::BufRead;
+
+use crate::cache::trace::{GeneratorSource, ReaderSource};
+use crate::cache::{Access, TraceSource};
+use crate::random::Rng;
+
+// cutting traces down to size, e.g. the loads from the first million accesses:
+// filter(slice(trace::open("huge.trace")?, 0, 1_000_000), |access| access.kind == AccessKind::Load)
+//
+// everything works one access at a time, so huge traces never have to fit in memory
+
+// accesses start..end of a trace (counting from 0)
+pub fn slice(mut source: impl TraceSource, start: usize, end: usize) -> impl TraceSource {
+    let mut position = 0;
+    GeneratorSource::new(move || {
+        while position < start {
+            source.next_access()?;
+            position += 1;
+        }
+        if position >= end {
+            return None;
+        }
+        position += 1;
+        source.next_access()
+    })
+}
+
+// every n-th access, starting with the first one
+pub fn sample_every(mut source: impl TraceSource, n: usize) -> impl TraceSource {
+    assert!(n > 0, "can't take every 0th access");
+    GeneratorSource::new(move || {
+        let access = source.next_access()?;
+        for _ in 1..n {
+            if source.next_access().is_none() {
+                break;
+            }
+        }
+        Some(access)
+    })
+}
+
+// every access has a fraction chance of being kept (the same seed keeps the same accesses)
+pub fn sample_random(mut source: impl TraceSource, fraction: f64, seed: u64) -> impl TraceSource {
+    let mut rng = Rng::new(seed);
+    GeneratorSource::new(move || loop {
+        let access = source.next_access()?;
+        if rng.chance(fraction) {
+            return Some(access);
+        }
+    })
+}
+
+// only the accesses keep says yes to
+pub fn filter(mut source: impl TraceSource, mut keep: impl FnMut(&Access) -> bool) -> impl TraceSource {
+    GeneratorSource::new(move || loop {
+        let access = source.next_access()?;
+        if keep(&access) {
+            return Some(access);
+        }
+    })
+}
+
+// several traces merged into one, quantum accesses from each trace in turn, like a scheduler
+// switching programs every quantum accesses (a trace that runs out just drops out), e.g. to
+// make a multiprogrammed workload
+pub fn interleave<'a, S: TraceSource + 'a>(sources: Vec<S>, quantum: usize) -> impl TraceSource + 'a {
+    GeneratorSource::new(round_robin(sources, quantum))
+}
+
+// the next access for round-robin: up to quantum from the trace whose turn it is, then on to the
+// next trace that hasn't run out
+fn round_robin<S: TraceSource>(sources: Vec<S>, quantum: usize) -> impl FnMut() -> Option<Access> {
+    assert!(quantum > 0, "a quantum has to be at least 1 access");
+    let mut sources: Vec<Option<S>> = sources.into_iter().map(Some).collect();
+    let (mut turn, mut taken) = (0, 0);
+
+    move || loop {
+        if sources.iter().all(Option::is_none) {
+            return None;
+        }
+        if taken == quantum {
+            turn = (turn + 1) % sources.len();
+            taken = 0;
+        }
+        match sources[turn].as_mut().and_then(TraceSource::next_access) {
+            Some(access) => {
+                taken += 1;
+                return Some(access);
+            }
+            // it's done, so it's the next trace's turn
+            None => {
+                sources[turn] = None;
+                taken = quantum;
+            }
+        }
+    }
+}
+
+// one access and when it happened, for merging traces by time (see interleave_by_time)
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct TimedAccess {
+    pub time: u64,
+    pub access: Access,
+}
+
+// a timestamped trace line is a normal trace line with the time at the end, e.g.
+// "l 0x0000AA40 4 1200" is a 4 byte load at time 1200 (the size isn't optional here). Everything
+// else that reads traces ignores the extra column
+impl std::str::FromStr for TimedAccess {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let line = line.trim();
+        if line.split_whitespace().count() != 4 {
+            return Err(format!("expected l or s, an address, a size and a timestamp, got {}", line));
+        }
+
+        let (access, time) = line.rsplit_once(char::is_whitespace).unwrap();
+        let time = time.parse().map_err(|e| format!("bad timestamp {}: {}", time, e))?;
+        Ok(TimedAccess { time, access: access.parse()? })
+    }
+}
+
+// a trace with a timestamp column (see TimedAccess), whose times never go backwards
+pub struct TimestampSource<R> {
+    reader: ReaderSource<R>,
+    last_time: u64,
+    // the first time that went backwards, the trace stops there (see error)
+    error: Option<String>,
+}
+
+impl<R: BufRead> TimestampSource<R> {
+    // e.g. TimestampSource::new(trace::open("a.trace")?)
+    pub fn new(reader: ReaderSource<R>) -> Self {
+        TimestampSource { reader, last_time: 0, error: None }
+    }
+
+    // what made the trace end early (None if it didn't)
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref().or(self.reader.error())
+    }
+
+    pub fn next_timed_access(&mut self) -> Option<TimedAccess> {
+        if self.error.is_some() {
+            return None;
+        }
+
+        let timed: TimedAccess = self.reader.next_parsed()?;
+        if timed.time < self.last_time {
+            self.error = Some(format!("{}: time {} comes after time {}", self.reader.location(), timed.time, self.last_time));
+            return None;
+        }
+        self.last_time = timed.time;
+        Some(timed)
+    }
+}
+
+// several timestamped traces merged into one in time order (ties go to the trace given first)
+//
+// only the next access of every trace is read ahead, so this streams like everything else.
+// Lend the sources (&mut) to check their errors afterwards
+pub fn interleave_by_time<'a, R: BufRead + 'a>(mut sources: Vec<&'a mut TimestampSource<R>>) -> impl TraceSource + 'a {
+    let mut next: Vec<Option<TimedAccess>> = sources.iter_mut().map(|source| source.next_timed_access()).collect();
+
+    GeneratorSource::new(move || {
+        let earliest = (0..next.len()).filter(|trace| next[*trace].is_some()).min_by_key(|trace| next[*trace].unwrap().time)?;
+        let access = next[earliest].take()?.access;
+        next[earliest] = sources[earliest].next_timed_access();
+        Some(access)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::trace::VecSource;
+    use crate::cache::AccessKind;
+
+    fn trace(addresses: impl IntoIterator<Item = u32>) -> Vec<Access> {
+        addresses.into_iter().map(|address| Access { kind: AccessKind::Load, address, size: 4 }).collect()
+    }
+
+    fn addresses(mut source: impl TraceSource) -> Vec<u32> {
+        std::iter::from_fn(|| source.next_access()).map(|access| access.address).collect()
+    }
+
+    #[test]
+    fn test_slice_sample_filter() {
+        let source = || VecSource::from(trace(0..10));
+        assert_eq!(addresses(slice(source(), 3, 6)), vec![3, 4, 5]);
+        assert_eq!(addresses(slice(source(), 8, 100)), vec![8, 9]);
+        assert_eq!(addresses(sample_every(source(), 4)), vec![0, 4, 8]);
+        assert_eq!(addresses(filter(source(), |access| access.address % 3 == 0)), vec![0, 3, 6, 9]);
+
+        let sampled = addresses(sample_random(VecSource::from(trace(0..1000)), 0.25, 7));
+        assert!((200..300).contains(&sampled.len()), "{}", sampled.len());
+        assert_eq!(sampled, addresses(sample_random(VecSource::from(trace(0..1000)), 0.25, 7)));
+    }
+
+    #[test]
+    fn test_interleave() {
+        let traces = || vec![VecSource::from(trace([1, 2, 3, 4])), VecSource::from(trace([10, 20]))];
+        let merged = |quantum| addresses(interleave(traces(), quantum));
+
+        assert_eq!(merged(1), vec![1, 10, 2, 20, 3, 4]);
+        assert_eq!(merged(3), vec![1, 2, 3, 10, 20, 4]);
+        assert!(addresses(interleave(Vec::<VecSource>::new(), 1)).is_empty());
+    }
+
+    #[test]
+    fn test_interleave_by_time() {
+        let timestamped = |file: &'static str| TimestampSource::new(ReaderSource::new(file.as_bytes(), "test"));
+        let mut a = timestamped("l 0x1 4 0\nl 0x2 4 5\n\nl 0x3 4 5\nl 0x4 4 30\n");
+        let mut b = timestamped("s 0x10 4 5\nl 0x20 4 12\n");
+        // the tie at 5 goes to a, and the extra column doesn't bother the normal parser
+        assert_eq!(addresses(interleave_by_time(vec![&mut a, &mut b])), vec![1, 2, 3, 16, 32, 4]);
+        assert_eq!("l 0x1 4 0".parse::<Access>().unwrap().address, 1);
+
+        let mut backwards = timestamped("l 0x1 4 10\nl 0x2 4 9\nl 0x3 4 11\n");
+        assert_eq!(addresses(interleave_by_time(vec![&mut backwards])), vec![1]);
+        assert_eq!(backwards.error(), Some("test line 2: time 9 comes after time 10"));
+        assert!(timestamped("l 0x1 4\n").next_timed_access().is_none());
+    }
+}
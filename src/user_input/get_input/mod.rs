@@ -45,6 +45,8 @@ where F: Fn(&String) -> Result<(), String>
 
         // reading one line from stdin should never error (if it did, crash)
         if let Err(e) = read_result { panic!("{}", e); }
+        // and once stdin is done there's nobody left to ask (asking again would loop forever)
+        if read_result.unwrap() == 0 { panic!("stdin ended before all the questions were answered"); }
 
         // check line against filter, if it passes we are done
         // if it doesn't pass we keep waiting for valid input (and print the error msg)
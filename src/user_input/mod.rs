@@ -2,8 +2,8 @@ mod get_input;
 
 use get_input::get_input;
 
-#[derive(Debug, PartialEq, Eq)]
-enum CacheType {
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CacheType {
     FullyAssociative(u32),
     DirectMapped(u32),
     SetAssociative(u32), // u32 here is the n in n-way
@@ -28,26 +28,48 @@ impl CacheType {
 pub enum ReplacementPolicy {
     LRU, 
     FIFO,
+    // re-reference interval prediction (see cache/replacement/rrip.rs)
+    SRRIP,
+    BRRIP,
+    DRRIP,
+    // LRU with adaptive insertion (see cache/replacement/insertion.rs)
+    LIP,
+    BIP,
+    DIP,
 }
 
 // basically the string "l" is the same as ReplacementPolicy::LRU
+// (and "srrip" is ReplacementPolicy::SRRIP, and so on, anything else is FIFO like the cpp)
 impl From<String> for ReplacementPolicy {
     fn from(value: String) -> Self {
-        if value.to_lowercase().trim() == "l" {
-            ReplacementPolicy::LRU
-        }
-        else {
-            ReplacementPolicy::FIFO
+        match value.to_lowercase().trim() {
+            "l" => ReplacementPolicy::LRU,
+            "srrip" => ReplacementPolicy::SRRIP,
+            "brrip" => ReplacementPolicy::BRRIP,
+            "drrip" => ReplacementPolicy::DRRIP,
+            "lip" => ReplacementPolicy::LIP,
+            "bip" => ReplacementPolicy::BIP,
+            "dip" => ReplacementPolicy::DIP,
+            _ => ReplacementPolicy::FIFO,
         }
     }
 }
 
+impl ReplacementPolicy {
+    // the cpp simulator only knows LRU and FIFO (so only those work in Mode::ReferenceCompatible)
+    pub fn in_cpp_simulator(&self) -> bool {
+        matches!(self, ReplacementPolicy::LRU | ReplacementPolicy::FIFO)
+    }
+}
+
 // contains all the config from the user in one place
 #[derive(Debug)]
 pub struct UserInput {
-    cache_size_exp: u32,
     line_size_exp: u32,
-    cache_type: CacheType,
+    // neither of these has to be a power of 2 (see from_sizes), but they are for everything
+    // the prompts and the cpp simulator can make
+    num_sets: u32,
+    ways: u32,
     replacement_policy: ReplacementPolicy,
 }
 
@@ -63,7 +85,11 @@ impl UserInput {
     // of course, use cache size, cache type, etc to find this info
     // 
     // there are some tests at the bottom of the file if you want to see an example
+    //
+    // this only works when the number of sets is a power of 2 (otherwise the set isn't a slice
+    // of the address, see set_and_tag)
     pub fn break_down_binary_address<'a>(&self, address: &'a String) -> (&'a str, &'a str, &'a str) {
+        assert!(self.num_sets.is_power_of_two(), "{} sets can't be sliced out of an address, use set_and_tag", self.num_sets);
         let number_of_tag_bits = self.tag_size() as usize;
         let number_of_set_bits = self.num_sets_exp() as usize;
         let number_of_offset_bits = self.line_size_exp as usize;
@@ -80,61 +106,185 @@ impl UserInput {
         return (tag, set, offset);
     }
 
-    fn new(cache_size_exp: u32, line_size_exp: u32, cache_type: String, replacement_policy: String) -> Self {
-        let cache_type: CacheType = match cache_type.to_lowercase().trim() {
-            "fa" => CacheType::FullyAssociative(cache_size_exp - line_size_exp),
+    // the set an address goes in and its tag, as numbers
+    //
+    // the set is the line number modulo the number of sets and the tag is what's left over. When
+    // the number of sets is a power of 2 that's exactly the set and tag bits of
+    // break_down_binary_address, and when it isn't every set still gets the same share of lines
+    pub fn set_and_tag(&self, address: u32) -> (u32, u32) {
+        let line_number = address >> self.line_size_exp;
+        (line_number % self.num_sets, line_number / self.num_sets)
+    }
+
+    // where in its line an address is, as a number (the offset bits of
+    // break_down_binary_address, which works for any number of sets)
+    pub fn line_offset(&self, address: u32) -> u32 {
+        address & (self.line_size() - 1)
+    }
+
+    // can the cpp simulator (and break_down_binary_address) handle this config?
+    pub fn is_power_of_two(&self) -> bool {
+        self.num_sets.is_power_of_two() && self.ways.is_power_of_two()
+    }
+
+    // build a UserInput without asking anyone anything
+    //
+    // handy for tests and for driving the simulator from code
+    // (the u32 in cache_type is the set size exponent, so FullyAssociative wants
+    // cache_size_exp - line_size_exp and DirectMapped wants 0)
+    pub fn with_config(cache_size_exp: u32, line_size_exp: u32, cache_type: CacheType, replacement_policy: ReplacementPolicy) -> Self {
+        assert!(line_size_exp <= cache_size_exp, "a line can't be bigger than the whole cache");
+        assert!(cache_type.set_size_exp() <= cache_size_exp - line_size_exp, "not enough lines for that many lines per set");
+
+        let num_sets_exp = cache_size_exp - line_size_exp - cache_type.set_size_exp();
+        UserInput {
+            line_size_exp,
+            num_sets: 2u32.pow(num_sets_exp),
+            ways: 2u32.pow(cache_type.set_size_exp()),
+            replacement_policy,
+        }
+    }
+
+    // build a UserInput from sizes in bytes and any number of ways, e.g. a 12 way 1.5MB cache
+    // with 64 byte lines is from_sizes(1536 * 1024, 64, 12, "l")
+    //
+    // lines still have to be a power of 2, but the cache size and ways don't. If the number of
+    // sets doesn't come out as a power of 2, sets are picked with a modulo (see set_and_tag).
+    // 1 way is direct mapped and as many ways as lines is fully associative
+    pub fn from_sizes(cache_size: u32, line_size: u32, ways: u32, replacement_policy: &str) -> Result<Self, String> {
+        if !line_size.is_power_of_two() {
+            return Err(format!("lines have to be a power of 2 bytes, {} isn't", line_size));
+        }
+        if line_size > cache_size {
+            return Err(format!("a {} byte line doesn't fit in a {} byte cache!", line_size, cache_size));
+        }
+        if !cache_size.is_multiple_of(line_size) {
+            return Err(format!("a {} byte cache doesn't split into {} byte lines", cache_size, line_size));
+        }
+
+        let num_lines = cache_size / line_size;
+        if ways == 0 {
+            return Err("a cache needs at least 1 line per set".into());
+        }
+        if ways > num_lines {
+            return Err(format!("a cache with {} lines can't have {} lines per set!", num_lines, ways));
+        }
+        if !num_lines.is_multiple_of(ways) {
+            return Err(format!("{} lines don't split into sets of {} (the cache size has to be a multiple of ways * line size)", num_lines, ways));
+        }
+
+        Ok(UserInput {
+            line_size_exp: line_size.ilog2(),
+            num_sets: num_lines / ways,
+            ways,
+            replacement_policy: replacement_policy.to_string().into(),
+        })
+    }
+
+    // the same answers you would give all_user_input, but without asking for them
+    // (ways_exp is the answer to the set associative question, so only sa needs it)
+    //
+    // returns an error message instead of asking again when an answer is bad
+    pub fn from_answers(cache_size_exp: u32, line_size_exp: u32, cache_type: &str, ways_exp: Option<u32>, replacement_policy: &str) -> Result<Self, String> {
+        if cache_size_exp > 32 {
+            return Err(format!("addresses are 32 bits, so a 2^{} byte cache is too big (2^32 bytes is all there is)", cache_size_exp));
+        }
+        if line_size_exp > cache_size_exp {
+            return Err(format!("a 2^{} byte line doesn't fit in a 2^{} byte cache!", line_size_exp, cache_size_exp));
+        }
+        let num_lines_exp = cache_size_exp - line_size_exp;
+        if num_lines_exp >= 32 {
+            return Err("2^32 lines is too many to count, use bigger lines".into());
+        }
+
+        let cache_type = match cache_type.to_lowercase().trim() {
+            "fa" => CacheType::FullyAssociative(num_lines_exp),
             "dm" => CacheType::DirectMapped(0),
-            "sa" => {
-                let msg = "Enter '1' for 2 lines per set, '2' for 4 lines per set, '3' for 8 lines per set, or '4' for 16 lines per set.";
-                let cache_size_exp = get_input(msg, |s| match s.trim() {
-                    "1" => Ok(()),
-                    "2" => Ok(()),
-                    "3" => Ok(()),
-                    "4" => Ok(()),
-                    _ => Err(format!("{} is not 1, 2, 3, or 4!", s))
-                });
-
-                CacheType::SetAssociative(cache_size_exp.parse().unwrap())
+            "sa" => match ways_exp {
+                Some(0) => return Err("set associative caches need at least 2 lines per set".into()),
+                Some(ways_exp) if ways_exp <= num_lines_exp => CacheType::SetAssociative(ways_exp),
+                Some(ways_exp) => return Err(format!("a cache with 2^{} lines can't have 2^{} lines per set!", num_lines_exp, ways_exp)),
+                None => return Err("set associative caches need the number of lines per set".into()),
             },
-            _ => unreachable!("should not happen ever")
+            other => return Err(format!("{} is not fa, dm, or sa!", other)),
         };
-        
-        UserInput { cache_size_exp, line_size_exp, cache_type, replacement_policy: replacement_policy.into() }
-    }
 
-    fn num_lines_exp(&self) -> u32 {
-        self.cache_size_exp - self.line_size_exp
+        // the tag is whatever's left of the address after the set and offset bits
+        let num_sets_exp = num_lines_exp - cache_type.set_size_exp();
+        if num_sets_exp + line_size_exp >= 32 {
+            return Err(format!("2^{} sets of 2^{} byte lines leave no bits of a 32 bit address for the tag", num_sets_exp, line_size_exp));
+        }
+
+        Ok(UserInput::with_config(cache_size_exp, line_size_exp, cache_type, replacement_policy.to_string().into()))
     }
 
-    fn num_sets_exp(&self) -> u32 {
-        self.num_lines_exp() - self.cache_type.set_size_exp()
+    // the number of bits it takes to write down a set index (rounded up when the number of sets
+    // isn't a power of 2)
+    pub(crate) fn num_sets_exp(&self) -> u32 {
+        self.num_sets.next_power_of_two().ilog2()
     }
 
-    fn num_sets(&self) -> u32 {
-        2u32.pow(self.num_sets_exp())
+    pub(crate) fn num_sets(&self) -> u32 {
+        self.num_sets
     }
 
-    fn tag_size(&self) -> u32 {
-        32 - self.num_sets_exp() - self.line_size_exp
+    // the number of bits in the biggest tag (see set_and_tag)
+    pub(crate) fn tag_size(&self) -> u32 {
+        let biggest_tag = (u32::MAX >> self.line_size_exp) / self.num_sets;
+        biggest_tag.checked_ilog2().map_or(0, |bits| bits + 1)
     }
 
     fn _set_size(&self) -> u32 {
         self.num_sets_exp()
     }
 
+    // 2^this number is the number of lines per set (setSizeExp in the cpp simulator)
+    pub(crate) fn set_size_exp(&self) -> u32 {
+        assert!(self.ways.is_power_of_two(), "the cpp simulator can't do {} lines per set", self.ways);
+        self.ways.ilog2()
+    }
+
     pub fn lines_per_set(&self) -> u32 {
-        self.num_lines() / self.num_sets()
+        self.ways
+    }
+
+    // in bytes
+    pub fn line_size(&self) -> u32 {
+        2u32.pow(self.line_size_exp)
     }
 
     fn _offset_size(&self) -> u32 {
         self.line_size_exp
     }
 
-    fn num_lines(&self) -> u32 {
-        2u32.pow(self.num_lines_exp())
+    pub(crate) fn num_lines(&self) -> u32 {
+        self.num_sets * self.ways
+    }
+
+    // in bytes
+    pub fn cache_size(&self) -> u64 {
+        self.num_lines() as u64 * self.line_size() as u64
     }
 }
 
+// a size in bytes, with an optional K or M on the end (e.g. 1536K)
+pub fn parse_size(value: &str) -> Result<u32, String> {
+    let value = value.trim().to_uppercase();
+    let (number, multiplier) = match value.strip_suffix('K') {
+        Some(number) => (number, 1 << 10),
+        None => match value.strip_suffix('M') {
+            Some(number) => (number, 1 << 20),
+            None => (value.as_str(), 1),
+        },
+    };
+
+    number
+        .parse::<u32>()
+        .map_err(|e| format!("{} is not a size: {}", value, e))?
+        .checked_mul(multiplier)
+        .ok_or(format!("{} is too big", value))
+}
+
 // the start of the program!
 // just ask the user a bunch of different things related to the cache config
 //
@@ -144,46 +294,84 @@ impl UserInput {
 pub fn all_user_input() -> UserInput{
     println!("This is an awesome cache simulator. By Jospeh Goodman.");
 
-    let msg = "Cache size is an exponent of 2.  E.g. if the exponent is 3, the cache is 2 to the 3, or 8 bytes\nEnter the exponent for the cache size:";
-    let cache_size_exp = get_input(msg, |s| { // this clousre just means that the input must be a
-                                              // number (i use it a lot in this function :P)
-        s.parse::<u32>()
-            .map_err(|e| e.to_string()) // if we get an error, make it a string
-            .map(|_| ()) // if we get no error, return () instead of the result of parsing (we
-                         // don't need the result)
-    });
-
-    let msg = "Line size is an exponent of 2.  E.g. if the exponent is 3, the cache is 2 to the 3, or 8 bytes\nEnter the exponent for the line size:";
-    let line_size_exp = get_input(msg, |s| {
-        s.parse::<u32>()
-            .map_err(|e| e.to_string()) // if we get an error, make it a string
-            .map(|_| ()) // if we get no error, return () instead of the result of parsing (we
-                         // don't need the result)
-    });
-
-    let msg = "What is the replacement policy? L or l for LRU, anything else for FIFO";
-    let replacement_policy = get_input(msg, |s| match s.to_lowercase().trim() {
-        _ => Ok(()), // this line is a weird way of saying there is no filter (all inputs are ok)
-    });
-
-    let msg = "Is this cache fully associative, direct mapped, or set associative?\n Enter FA, DM, or SA";
-    let cache_type = get_input(msg, |s| match s.to_lowercase().trim() {
-        "fa" => Ok(()),
-        "dm" => Ok(()),
-        "sa" => Ok(()),
-        _ => Err(format!("{} is not fa, dm, or sa!", s)),
-    });
-
-    UserInput::new(cache_size_exp.parse().unwrap(), line_size_exp.parse().unwrap(), cache_type, replacement_policy)
+    // every answer gets checked on its own, and then all together by from_answers (a line
+    // bigger than the cache only shows up then), in which case we start over
+    loop {
+        let msg = "Cache size is an exponent of 2.  E.g. if the exponent is 3, the cache is 2 to the 3, or 8 bytes\nEnter the exponent for the cache size:";
+        let cache_size_exp = get_input(msg, |s| { // this clousre just means that the input must be a
+                                                  // number (i use it a lot in this function :P)
+            s.parse::<u32>()
+                .map_err(|e| e.to_string()) // if we get an error, make it a string
+                .map(|_| ()) // if we get no error, return () instead of the result of parsing (we
+                             // don't need the result)
+        });
+
+        let msg = "Line size is an exponent of 2.  E.g. if the exponent is 3, the cache is 2 to the 3, or 8 bytes\nEnter the exponent for the line size:";
+        let line_size_exp = get_input(msg, |s| {
+            s.parse::<u32>()
+                .map_err(|e| e.to_string()) // if we get an error, make it a string
+                .map(|_| ()) // if we get no error, return () instead of the result of parsing (we
+                             // don't need the result)
+        });
+
+        let msg = "What is the replacement policy? L or l for LRU, SRRIP, BRRIP, DRRIP, LIP, BIP or DIP for those, anything else for FIFO";
+        let replacement_policy = get_input(msg, |s| match s.to_lowercase().trim() {
+            _ => Ok(()), // this line is a weird way of saying there is no filter (all inputs are ok)
+        });
+
+        let msg = "Is this cache fully associative, direct mapped, or set associative?\n Enter FA, DM, or SA";
+        let cache_type = get_input(msg, |s| match s.to_lowercase().trim() {
+            "fa" => Ok(()),
+            "dm" => Ok(()),
+            "sa" => Ok(()),
+            _ => Err(format!("{} is not fa, dm, or sa!", s)),
+        });
+
+        // the same answers as the cpp, but anything up to the number of lines works
+        // (ways that aren't a power of 2 need UserInput::from_sizes, e.g. --ways)
+        let ways_exp = (cache_type.to_lowercase() == "sa").then(|| {
+            let msg = "Enter '1' for 2 lines per set, '2' for 4 lines per set, '3' for 8 lines per set, or '4' for 16 lines per set (or any n for 2^n lines per set).";
+            let ways_exp = get_input(msg, |s| match s.parse::<u32>() {
+                Ok(1..) => Ok(()),
+                _ => Err(format!("{} is not a number from 1 up!", s)),
+            });
+            ways_exp.parse().unwrap()
+        });
+
+        // the filters made sure these parse
+        let (cache_size_exp, line_size_exp) = (cache_size_exp.parse().unwrap(), line_size_exp.parse().unwrap());
+        match UserInput::from_answers(cache_size_exp, line_size_exp, &cache_type, ways_exp, &replacement_policy) {
+            Ok(ui) => return ui,
+            Err(e) => println!("{}\nlet's start over\n", e),
+        }
+    }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
+    use proptest::prelude::*;
+
+    // a random (but valid) cache config, for property tests
+    //
+    // kept small so random traces actually get some hits
+    pub(crate) fn any_config() -> impl Strategy<Value = (u32, u32, CacheType, ReplacementPolicy)> {
+        (0u32..=4, 0u32..=8)
+            .prop_flat_map(|(line_size_exp, lines_exp)| {
+                let cache_type = prop_oneof![
+                    Just(CacheType::FullyAssociative(lines_exp)),
+                    Just(CacheType::DirectMapped(0)),
+                    (0..=lines_exp.min(4)).prop_map(CacheType::SetAssociative),
+                ];
+                let policy = prop_oneof![Just(ReplacementPolicy::LRU), Just(ReplacementPolicy::FIFO)];
+
+                (Just(line_size_exp + lines_exp), Just(line_size_exp), cache_type, policy)
+            })
+    }
 
     #[test]
     fn test_decompose_binary_address_with_fa() {
-        let test_input = UserInput::new(5, 2, "fa".into(), "l".into());
+        let test_input = UserInput::from_answers(5, 2, "fa", None, "l").unwrap();
         // 0x1fffff50
         let binary_address = "00011111111111111111111101010000".to_string();
         let (tag, set, offset) = test_input.break_down_binary_address(&binary_address);
@@ -194,7 +382,7 @@ mod tests {
 
     #[test]
     fn test_decompose_binary_address_with_fa_again() {
-        let test_input = UserInput::new(5, 2, "fa".into(), "l".into());
+        let test_input = UserInput::from_answers(5, 2, "fa", None, "l").unwrap();
         // 0x1fabce50
         let binary_address = "00011111101010111100111001010000".to_string();
         let (tag, set, offset) = test_input.break_down_binary_address(&binary_address);
@@ -205,7 +393,7 @@ mod tests {
 
     #[test]
     fn test_decompose_binary_address_with_fa_again2() {
-        let test_input = UserInput::new(5, 2, "fa".into(), "l".into());
+        let test_input = UserInput::from_answers(5, 2, "fa", None, "l").unwrap();
         // 0x1fabce50
         let binary_address = "00011111111111111111011000101000".to_string();
         let (tag, set, offset) = test_input.break_down_binary_address(&binary_address);
@@ -216,7 +404,7 @@ mod tests {
 
     #[test]
     fn test_decompose_binary_address_with_fa_general() {
-        let test_input = UserInput::new(5, 2, "fa".into(), "l".into());
+        let test_input = UserInput::from_answers(5, 2, "fa", None, "l").unwrap();
         // 0x1fabce50
         let binary_address = "11010110110110110110101010101010".to_string();
         let (tag, _set, offset) = test_input.break_down_binary_address(&binary_address);
@@ -225,7 +413,7 @@ mod tests {
 
     #[test]
     fn test_decompose_binary_address_with_dm() {
-        let test_input = UserInput::new(5, 2, "dm".into(), "l".into());
+        let test_input = UserInput::from_answers(5, 2, "dm", None, "l").unwrap();
         // 0x1fffff50
         let binary_address = "00011111111111111111111101010000".to_string();
         let (tag, set, offset) = test_input.break_down_binary_address(&binary_address);
@@ -233,4 +421,65 @@ mod tests {
         assert_eq!(set, "100");
         assert_eq!(offset, "00");
     }
+
+    #[test]
+    fn test_from_sizes() {
+        // a 12 way 1.5MB L2 has 2048 sets
+        let l2 = UserInput::from_sizes(1536 * 1024, 64, 12, "l").unwrap();
+        assert_eq!((l2.num_sets(), l2.lines_per_set(), l2.cache_size()), (2048, 12, 1536 * 1024));
+        assert!(!l2.is_power_of_two());
+
+        // 3 sets: the set is the line number mod 3 and the tag is the rest
+        let odd = UserInput::from_sizes(96, 16, 2, "l").unwrap();
+        assert_eq!(odd.set_and_tag(0x75), (1, 2));
+        assert_eq!((odd.num_sets_exp(), odd.tag_size()), (2, 27));
+
+        assert_eq!(UserInput::from_sizes(96, 24, 1, "l").unwrap_err(), "lines have to be a power of 2 bytes, 24 isn't");
+        assert_eq!(UserInput::from_sizes(100, 16, 1, "l").unwrap_err(), "a 100 byte cache doesn't split into 16 byte lines");
+        assert!(UserInput::from_sizes(96, 16, 4, "l").unwrap_err().starts_with("6 lines don't split into sets of 4"));
+        assert!(UserInput::from_sizes(96, 16, 0, "l").is_err());
+        assert!(UserInput::from_sizes(96, 16, 7, "l").is_err());
+    }
+
+    #[test]
+    fn test_from_answers_fits_in_32_bits() {
+        let ui = UserInput::from_answers(16, 6, "sa", Some(3), "l").unwrap();
+        assert_eq!((ui.num_sets(), ui.lines_per_set()), (128, 8));
+
+        assert!(UserInput::from_answers(33, 0, "dm", None, "l").unwrap_err().starts_with("addresses are 32 bits"));
+        assert_eq!(UserInput::from_answers(32, 0, "fa", None, "l").unwrap_err(), "2^32 lines is too many to count, use bigger lines");
+        assert_eq!(UserInput::from_answers(32, 4, "dm", None, "l").unwrap_err(), "2^28 sets of 2^4 byte lines leave no bits of a 32 bit address for the tag");
+        // 1 tag bit is enough
+        assert_eq!(UserInput::from_answers(31, 4, "dm", None, "l").unwrap().tag_size(), 1);
+        assert!(UserInput::from_answers(32, 4, "sa", Some(1), "l").is_ok());
+    }
+
+    // powers of 2 give the same set and tag both ways
+    #[test]
+    fn test_set_and_tag_matches_binary() {
+        let test_input = UserInput::from_answers(5, 2, "dm", None, "l").unwrap();
+        let binary_address = "00011111111111111111111101010000".to_string();
+        let (tag, set, _) = test_input.break_down_binary_address(&binary_address);
+        let expected = (u32::from_str_radix(set, 2).unwrap(), u32::from_str_radix(tag, 2).unwrap());
+        assert_eq!(test_input.set_and_tag(0x1fffff50), expected);
+    }
+
+    proptest! {
+        #[test]
+        fn prop_decomposed_address_reassembles((cache_size_exp, line_size_exp, cache_type, policy) in any_config(), address: u32) {
+            let test_input = UserInput::with_config(cache_size_exp, line_size_exp, cache_type, policy);
+            let binary_address = format!("{:032b}", address);
+            let (tag, set, offset) = test_input.break_down_binary_address(&binary_address);
+
+            prop_assert_eq!(tag.len() as u32, test_input.tag_size());
+            prop_assert_eq!(set.len() as u32, test_input.num_sets_exp());
+            prop_assert_eq!(offset.len() as u32, line_size_exp);
+            prop_assert_eq!(String::from(tag) + set + offset, binary_address.clone());
+
+            // and the numbers everything else uses are the same bits
+            let number = |bits: &str| if bits.is_empty() { 0 } else { u32::from_str_radix(bits, 2).unwrap() };
+            prop_assert_eq!(test_input.set_and_tag(address), (number(set), number(tag)));
+            prop_assert_eq!(test_input.line_offset(address), number(offset));
+        }
+    }
 }
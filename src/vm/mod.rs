@@ -0,0 +1,263 @@
+mod tlb;
+
+use std::collections::{HashMap, HashSet};
+
+use crate::random::Rng;
+use crate::user_input::UserInput;
+
+use tlb::Tlb;
+
+// how virtual pages get their physical frames (always the same answer for the same setup)
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Mapping {
+    // the physical address is the virtual address
+    Identity,
+    // every new page gets a random free frame (after a few taken ones in a row, the next free
+    // frame after a random one)
+    Random { seed: u64 },
+    // every new page gets the next free frame with the same color (page number % colors), so
+    // pages that don't fight over cache sets in virtual memory don't in physical memory either
+    // (see page_colors)
+    PageColoring { colors: u32 },
+}
+
+// how many page colors a cache has: how many pages fit side by side in one way of the cache
+// (at least 1, and rounded down to a power of 2 when the number of sets isn't one)
+pub fn page_colors(user_input: &UserInput, page_size_exp: u32) -> u32 {
+    let way_size = user_input.num_sets() as u64 * user_input.line_size() as u64;
+    let colors = (way_size >> page_size_exp).max(1);
+    1 << colors.ilog2()
+}
+
+// how translating went (see SimResults::tlb)
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct TlbStats {
+    pub translations: u32,
+    // by TLB level (hits[0] is the first level)
+    pub hits: Vec<u32>,
+    pub misses: Vec<u32>,
+    // misses in every TLB level, so the page table had to be walked
+    pub walks: u32,
+    // memory references the page table walks made (one per page table level)
+    pub walk_references: u32,
+}
+
+impl TlbStats {
+    // what happened between `before` and now
+    pub(crate) fn since(&self, before: &TlbStats) -> TlbStats {
+        let minus = |now: &[u32], then: &[u32]| now.iter().zip(then).map(|(now, then)| now - then).collect();
+
+        TlbStats {
+            translations: self.translations - before.translations,
+            hits: minus(&self.hits, &before.hits),
+            misses: minus(&self.misses, &before.misses),
+            walks: self.walks - before.walks,
+            walk_references: self.walk_references - before.walk_references,
+        }
+    }
+}
+
+// turns the virtual addresses in a trace into physical ones before they get to the cache
+// (see Cache::set_mmu)
+//
+// translations are looked up in each TLB level in order. A hit fills the levels before it, and
+// a miss everywhere walks the page table (page_table_levels memory references) and fills every
+// level. Page tables are never written to memory, the walker just counts what it would read.
+//
+// e.g. 4KB pages with a 64 entry 4 way L1 TLB and a 1024 entry 8 way L2 TLB:
+// Mmu::new(12, Mapping::Identity).with_tlb(64, 4).with_tlb(1024, 8)
+#[derive(Debug)]
+pub struct Mmu {
+    page_size_exp: u32,
+    mapping: Mapping,
+    tlbs: Vec<Tlb>,
+    page_table_levels: u32,
+    // every page that has been touched and its frame
+    page_table: HashMap<u32, u32>,
+    used_frames: HashSet<u32>,
+    // for Mapping::PageColoring, the next frame number (divided by colors) of every color
+    next_frame_of_color: Vec<u32>,
+    rng: Rng,
+    stats: TlbStats,
+}
+
+impl Mmu {
+    // page_size_exp is 2^page_size_exp bytes per page (12 is 4KB)
+    pub fn new(page_size_exp: u32, mapping: Mapping) -> Self {
+        assert!((1..32).contains(&page_size_exp), "pages have to be 2 bytes to 2GB");
+
+        let (seed, colors) = match mapping {
+            Mapping::Identity => (0, 1),
+            Mapping::Random { seed } => (seed, 1),
+            Mapping::PageColoring { colors } => {
+                assert!(colors.is_power_of_two(), "the number of colors has to be a power of 2");
+                assert!(colors <= 1 << (32 - page_size_exp), "more colors than frames");
+                (0, colors)
+            }
+        };
+
+        Mmu {
+            page_size_exp,
+            mapping,
+            tlbs: Vec::new(),
+            // enough 1024 entry tables to cover the virtual page number (2 for 4KB pages)
+            page_table_levels: (32 - page_size_exp).div_ceil(10),
+            page_table: HashMap::new(),
+            used_frames: HashSet::new(),
+            next_frame_of_color: vec![0; colors as usize],
+            rng: Rng::new(seed),
+            stats: TlbStats::default(),
+        }
+    }
+
+    // add a TLB level after the ones that are already there
+    pub fn with_tlb(mut self, entries: usize, ways: usize) -> Self {
+        self.tlbs.push(Tlb::new(entries, ways));
+        self.stats.hits.push(0);
+        self.stats.misses.push(0);
+        self
+    }
+
+    // how many memory references a page table walk takes
+    pub fn with_page_table_levels(mut self, levels: u32) -> Self {
+        assert!(levels > 0, "the page table needs at least one level");
+        self.page_table_levels = levels;
+        self
+    }
+
+    pub fn stats(&self) -> &TlbStats {
+        &self.stats
+    }
+
+    // the frame for a page that isn't in the page table yet (or an error if there aren't any
+    // left)
+    fn allocate_frame(&mut self, page: u32) -> Result<u32, String> {
+        let num_frames = 1u64 << (32 - self.page_size_exp);
+        if self.used_frames.len() as u64 >= num_frames {
+            return Err(format!("all {} physical frames are taken", num_frames));
+        }
+
+        let frame = match self.mapping {
+            Mapping::Identity => page,
+            Mapping::Random { .. } => {
+                // guessing gets slow when most frames are taken, so after a few misses just look
+                // for the next free one (there is one, see above)
+                let guesses = (0..16).map(|_| self.rng.below(num_frames) as u32).find(|frame| !self.used_frames.contains(frame));
+                guesses.unwrap_or_else(|| {
+                    let start = self.rng.below(num_frames);
+                    (0..num_frames)
+                        .map(|i| ((start + i) % num_frames) as u32)
+                        .find(|frame| !self.used_frames.contains(frame))
+                        .unwrap()
+                })
+            }
+            Mapping::PageColoring { colors } => {
+                let color = page % colors;
+                let next = &mut self.next_frame_of_color[color as usize];
+                let frame = next
+                    .checked_mul(colors)
+                    .and_then(|frame| frame.checked_add(color))
+                    .filter(|frame| (*frame as u64) < num_frames)
+                    .ok_or(format!("all the physical frames of color {} are taken", color))?;
+                *next += 1;
+                frame
+            }
+        };
+
+        self.used_frames.insert(frame);
+        Ok(frame)
+    }
+
+    // the physical address for a virtual address (or an error if the page is new and there's
+    // no frame left for it)
+    pub fn translate(&mut self, virtual_address: u32) -> Result<u32, String> {
+        let page = virtual_address >> self.page_size_exp;
+        let offset = virtual_address & ((1 << self.page_size_exp) - 1);
+        self.stats.translations += 1;
+
+        // the first TLB level that has it
+        let mut found = None;
+        for (level, tlb) in self.tlbs.iter_mut().enumerate() {
+            if let Some(frame) = tlb.lookup(page) {
+                self.stats.hits[level] += 1;
+                found = Some((level, frame));
+                break;
+            }
+            self.stats.misses[level] += 1;
+        }
+
+        let (hit_level, frame) = match found {
+            Some(found) => found,
+            None => {
+                self.stats.walks += 1;
+                self.stats.walk_references += self.page_table_levels;
+                let frame = match self.page_table.get(&page) {
+                    Some(frame) => *frame,
+                    None => {
+                        let frame = self.allocate_frame(page)?;
+                        self.page_table.insert(page, frame);
+                        frame
+                    }
+                };
+                (self.tlbs.len(), frame)
+            }
+        };
+
+        self.tlbs[..hit_level].iter_mut().for_each(|tlb| tlb.insert(page, frame));
+        Ok((frame << self.page_size_exp) | offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_two_level_tlb() {
+        // 4KB pages, a 1 entry L1 and a 4 entry L2
+        let mut mmu = Mmu::new(12, Mapping::Identity).with_tlb(1, 1).with_tlb(4, 4);
+
+        assert_eq!(mmu.translate(0x1234), Ok(0x1234));
+        mmu.translate(0x2000).unwrap();
+        // page 1 got kicked out of the L1 but the L2 still has it
+        mmu.translate(0x1000).unwrap();
+
+        assert_eq!(
+            *mmu.stats(),
+            TlbStats { translations: 3, hits: vec![0, 1], misses: vec![3, 2], walks: 2, walk_references: 4 }
+        );
+    }
+
+    #[test]
+    fn test_mappings() {
+        // page coloring keeps the color (the low bits of the page number)
+        let mut coloring = Mmu::new(12, Mapping::PageColoring { colors: 4 });
+        let frames: Vec<_> = [0x7000, 0x3000, 0x5000].iter().map(|address| coloring.translate(*address).unwrap() >> 12).collect();
+        assert_eq!(frames, vec![3, 7, 1]);
+
+        // random is the same every time and never hands out a frame twice
+        let translate = |seed| {
+            let mut random = Mmu::new(4, Mapping::Random { seed });
+            (0..1000).map(|page| random.translate(page << 4).unwrap()).collect::<Vec<_>>()
+        };
+        let frames = translate(7);
+        assert_eq!(frames, translate(7));
+        assert_eq!(frames.iter().collect::<HashSet<_>>().len(), 1000);
+
+    }
+
+    #[test]
+    fn test_running_out_of_frames() {
+        // 1MB pages make 4096 frames, with only frame 1234 left random still finds it
+        let mut random = Mmu::new(20, Mapping::Random { seed: 1 });
+        random.used_frames.extend((0..4096).filter(|frame| *frame != 1234));
+        assert_eq!(random.translate(0x0010_0000), Ok(1234 << 20));
+        assert!(random.translate(0x0020_0000).is_err());
+
+        // 2GB pages make 2 frames, 1 of each color
+        let mut coloring = Mmu::new(31, Mapping::PageColoring { colors: 2 });
+        coloring.next_frame_of_color[1] = 1;
+        assert!(coloring.translate(0x8000_0000).is_err());
+        assert_eq!(coloring.translate(0x1234), Ok(0x1234));
+    }
+}
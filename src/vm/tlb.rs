@@ -0,0 +1,69 @@
+// one level of TLB: a small set associative cache of virtual page number -> physical frame
+// number, with LRU replacement
+#[derive(Debug)]
+pub(crate) struct Tlb {
+    ways: usize,
+    // one Vec per set of (virtual page, physical frame, last used)
+    sets: Vec<Vec<(u32, u32, u64)>>,
+    clock: u64,
+}
+
+impl Tlb {
+    // entries / ways has to be a whole number of sets
+    pub(crate) fn new(entries: usize, ways: usize) -> Self {
+        assert!(entries > 0 && ways > 0, "a TLB needs at least one entry");
+        assert!(entries.is_multiple_of(ways), "{} entries don't split into {} way sets", entries, ways);
+
+        Tlb { ways, sets: vec![Vec::with_capacity(ways); entries / ways], clock: 0 }
+    }
+
+    fn set(&mut self, page: u32) -> &mut Vec<(u32, u32, u64)> {
+        let num_sets = self.sets.len();
+        &mut self.sets[page as usize % num_sets]
+    }
+
+    // the frame for a page, if this TLB has it
+    pub(crate) fn lookup(&mut self, page: u32) -> Option<u32> {
+        self.clock += 1;
+        let clock = self.clock;
+
+        let entry = self.set(page).iter_mut().find(|(entry_page, _, _)| *entry_page == page)?;
+        entry.2 = clock;
+        Some(entry.1)
+    }
+
+    // remember a translation (kicking out the least recently used entry in the set if it's full)
+    pub(crate) fn insert(&mut self, page: u32, frame: u32) {
+        self.clock += 1;
+        let clock = self.clock;
+        let ways = self.ways;
+        let set = self.set(page);
+
+        if set.len() < ways {
+            set.push((page, frame, clock));
+        } else {
+            let oldest = set.iter_mut().min_by_key(|(_, _, last_used)| *last_used).unwrap();
+            *oldest = (page, frame, clock);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tlb_lru() {
+        // 2 sets of 2
+        let mut tlb = Tlb::new(4, 2);
+        tlb.insert(0, 10);
+        tlb.insert(2, 12);
+        assert_eq!(tlb.lookup(0), Some(10));
+
+        // page 4 goes in set 0 too, and page 2 is the least recently used there
+        tlb.insert(4, 14);
+        assert_eq!(tlb.lookup(2), None);
+        assert_eq!(tlb.lookup(0), Some(10));
+        assert_eq!(tlb.lookup(4), Some(14));
+    }
+}
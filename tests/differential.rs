@@ -0,0 +1,147 @@
+// differential tests against default_cache_sim.cpp
+//
+// tests/golden/ holds the hits and accesses the cpp simulator reported for every trace in
+// trace_files/ and a grid of configs (see tests/golden/capture.sh).
+// If the port ever disagrees with the original, these tests will say so.
+//
+// reference.txt is checked against the normal cache, reference_compat.txt (which includes the
+// cpp's quirks) against Mode::ReferenceCompatible
+
+use std::io::BufReader;
+
+use cda_cache_sim::cache::{Cache, Mode, SimResults};
+use cda_cache_sim::user_input::{CacheType, ReplacementPolicy, UserInput};
+
+// one line of a golden file
+struct GoldenCase {
+    trace: String,
+    cache_size_exp: u32,
+    line_size_exp: u32,
+    cache_type: String,
+    policy: String,
+    hits: u32,
+    accesses: u32,
+}
+
+impl GoldenCase {
+    fn user_input(&self) -> UserInput {
+        let cache_type = match self.cache_type.as_str() {
+            "fa" => CacheType::FullyAssociative(self.cache_size_exp - self.line_size_exp),
+            "dm" => CacheType::DirectMapped(0),
+            sa => CacheType::SetAssociative(
+                sa.strip_prefix("sa")
+                    .and_then(|ways_exp| ways_exp.parse().ok())
+                    .expect("cache type should be fa, dm, or sa<n>"),
+            ),
+        };
+
+        UserInput::with_config(
+            self.cache_size_exp,
+            self.line_size_exp,
+            cache_type,
+            ReplacementPolicy::from(self.policy.clone()),
+        )
+    }
+
+    fn trace_path(&self) -> String {
+        format!("{}/trace_files/{}.trace", env!("CARGO_MANIFEST_DIR"), self.trace)
+    }
+}
+
+impl std::fmt::Display for GoldenCase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {}",
+            self.trace, self.cache_size_exp, self.line_size_exp, self.cache_type, self.policy
+        )
+    }
+}
+
+fn load_golden_file(name: &str) -> Vec<GoldenCase> {
+    let path = format!("{}/tests/golden/{}", env!("CARGO_MANIFEST_DIR"), name);
+    std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("could not read {}: {}", path, e))
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            assert_eq!(fields.len(), 7, "bad golden line: {}", line);
+
+            GoldenCase {
+                trace: fields[0].into(),
+                cache_size_exp: fields[1].parse().unwrap(),
+                line_size_exp: fields[2].parse().unwrap(),
+                cache_type: fields[3].into(),
+                policy: fields[4].into(),
+                hits: fields[5].parse().unwrap(),
+                accesses: fields[6].parse().unwrap(),
+            }
+        })
+        .collect()
+}
+
+// run every case in a golden file and complain about all the ones that don't match
+fn check_golden_file(name: &str, mode: Mode) {
+    check_golden_file_with(name, mode, |cache, trace_path| cache.simulate_trace_file(trace_path).unwrap());
+}
+
+// same as check_golden_file, but simulate gets to decide how the trace gets to the cache
+fn check_golden_file_with(name: &str, mode: Mode, simulate: impl Fn(&mut Cache, &str) -> SimResults) {
+    let cases = load_golden_file(name);
+    assert!(!cases.is_empty());
+
+    let mut mismatches = Vec::new();
+    for case in &cases {
+        let ui = case.user_input();
+        let mut cache = Cache::with_mode(&ui, mode);
+        let results = simulate(&mut cache, &case.trace_path());
+
+        if results.hits != case.hits || results.accesses != case.accesses {
+            mismatches.push(format!(
+                "{}: expected {} hits / {} accesses, got {} / {}",
+                case, case.hits, case.accesses, results.hits, results.accesses
+            ));
+        }
+    }
+
+    assert!(mismatches.is_empty(), "{} mismatches in {}:\n{}", mismatches.len(), name, mismatches.join("\n"));
+}
+
+#[test]
+fn golden_files_cover_every_trace() {
+    for name in ["reference.txt", "reference_compat.txt"] {
+        let cases = load_golden_file(name);
+
+        for entry in std::fs::read_dir(format!("{}/trace_files", env!("CARGO_MANIFEST_DIR"))).unwrap() {
+            let path = entry.unwrap().path();
+            let trace = path.file_stem().unwrap().to_str().unwrap();
+            assert!(
+                cases.iter().any(|case| case.trace == trace),
+                "{} has no results in {}, re-run tests/golden/capture.sh",
+                trace,
+                name
+            );
+        }
+    }
+}
+
+#[test]
+fn matches_reference_simulator() {
+    check_golden_file("reference.txt", Mode::Correct);
+}
+
+#[test]
+fn reference_compatible_mode_matches_reference_simulator_exactly() {
+    check_golden_file("reference_compat.txt", Mode::ReferenceCompatible);
+}
+
+// `cargo run -- --reference -` reads the trace from stdin with simulate_reader, which has to keep
+// the cpp's quirks (like the extra access from a trailing newline) too
+#[test]
+fn reference_compatible_mode_matches_reference_simulator_from_a_reader() {
+    check_golden_file_with("reference_compat.txt", Mode::ReferenceCompatible, |cache, trace_path| {
+        let file = std::fs::File::open(trace_path).unwrap();
+        cache.simulate_reader(BufReader::new(file), "stdin").unwrap()
+    });
+}
@@ -0,0 +1,69 @@
+#!/bin/sh
+# regenerates the golden files in tests/golden/ by running default_cache_sim.cpp over every
+# trace in trace_files/ for a grid of configs
+#
+# run from the root of the repo: sh tests/golden/capture.sh
+#
+# the cpp simulator loops on while(!eof), so a trace ending in a newline gets one extra
+# access (it re-reads the last address).
+#  - reference.txt is captured with the final newline stripped, so it only tests the simulation
+#  - reference_compat.txt is captured from the traces as they are, quirks and all
+
+set -e
+
+tmp=$(mktemp -d)
+trap 'rm -rf "$tmp"' EXIT
+
+g++ -O0 -o "$tmp/refsim" default_cache_sim.cpp
+
+# capture <output file> <directory with the traces> <line size exponents>
+capture() {
+    out=$1
+    traces=$2
+    line_exps=$3
+
+    echo "# captured from default_cache_sim.cpp by tests/golden/capture.sh (do not edit by hand)" > "$out"
+    echo "# trace cache_size_exp line_size_exp type policy hits accesses" >> "$out"
+
+    for trace in "$traces"/*.trace; do
+        name=$(basename "$trace" .trace)
+
+        for line_exp in $line_exps; do
+            for lines_exp in 0 1 2 3 4 5; do
+                cache_exp=$((line_exp + lines_exp))
+
+                types="fa dm"
+                for ways_exp in 1 2 3 4; do
+                    if [ "$ways_exp" -le "$lines_exp" ]; then
+                        types="$types sa$ways_exp"
+                    fi
+                done
+
+                for type in $types; do
+                    case $type in
+                        fa) answers="y" ;;
+                        dm) answers="n\ny" ;;
+                        sa*) answers="n\nn\n${type#sa}" ;;
+                    esac
+
+                    for policy in l f; do
+                        result=$(printf "$cache_exp\n$line_exp\n$answers\n$policy\n$trace\n" | "$tmp/refsim" | tail -n 1)
+                        hits=$(echo "$result" | awk '{print $2}')
+                        accesses=$(echo "$result" | awk '{print $4}')
+                        echo "$name $cache_exp $line_exp $type $policy $hits $accesses" >> "$out"
+                    done
+                done
+            done
+        done
+    done
+}
+
+mkdir "$tmp/stripped"
+for trace in trace_files/*.trace; do
+    printf '%s' "$(cat "$trace")" > "$tmp/stripped/$(basename "$trace")"
+done
+
+capture tests/golden/reference.txt "$tmp/stripped" "1 2 3"
+
+# line size 0 makes the tag 32 bits wide, which overflows the cpp's int tags
+capture tests/golden/reference_compat.txt trace_files "0 1 2 3"